@@ -0,0 +1,87 @@
+use std::collections::HashSet;
+
+use aws_sg_cleanup::security::{ExistingGroup, SecurityGroups};
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+/// Builds an inventory of `size` groups spread over a few regions. Most groups reference
+/// a handful of others, a fraction are attached directly to services, and some form
+/// reference cycles.
+fn synthetic_inventory(size: usize) -> SecurityGroups {
+    let mut rng = StdRng::seed_from_u64(size as u64);
+    let regions = ["us-east-1", "eu-west-1", "ap-southeast-2"];
+    let ids = (0..size)
+        .map(|i| format!("sg-{:08x}", i))
+        .collect::<Vec<_>>();
+
+    let existing_groups = ids
+        .iter()
+        .enumerate()
+        .map(|(i, group_id)| {
+            let references: HashSet<String> = (0..rng.gen_range(0..4))
+                .map(|_| ids[rng.gen_range(0..size)].clone())
+                .filter(|other| other != group_id)
+                .collect();
+            ExistingGroup {
+                region: regions[i % regions.len()].to_string(),
+                group_id: group_id.clone(),
+                group_name: group_id.clone(),
+                group_description: group_id.clone(),
+                references,
+                ..ExistingGroup::default()
+            }
+        })
+        .collect();
+
+    let external_references = ids
+        .iter()
+        .enumerate()
+        .filter_map(|(i, group_id)| {
+            if !rng.gen_bool(0.1) {
+                return None;
+            }
+            let service = ["ec2", "alb", "rds", "lambda"][rng.gen_range(0..4)];
+            Some((
+                group_id.clone(),
+                vec![format!("{}@{}", service, regions[i % regions.len()]).into()],
+            ))
+        })
+        .collect();
+
+    SecurityGroups {
+        external_references,
+        existing_groups,
+        ..SecurityGroups::default()
+    }
+}
+
+fn find_unused(c: &mut Criterion) {
+    let mut group = c.benchmark_group("find_unused");
+    group.sample_size(10);
+    for size in [1_000, 10_000, 30_000] {
+        let inventory = synthetic_inventory(size);
+        group.bench_with_input(
+            BenchmarkId::from_parameter(size),
+            &inventory,
+            |b, inventory| b.iter(|| black_box(inventory.find_unused().len())),
+        );
+    }
+    group.finish();
+}
+
+fn referencing_services(c: &mut Criterion) {
+    let inventory = synthetic_inventory(30_000);
+    c.bench_function("referencing_services/30000", |b| {
+        b.iter(|| {
+            let graph = inventory.reference_graph();
+            inventory
+                .existing_groups
+                .iter()
+                .map(|group| graph.referencing_services(&group.group_id).len())
+                .sum::<usize>()
+        })
+    });
+}
+
+criterion_group!(benches, find_unused, referencing_services);
+criterion_main!(benches);
@@ -0,0 +1,283 @@
+use std::future::Future;
+use std::time::SystemTime;
+
+use anyhow::{bail, Context};
+use aws_config::{meta::credentials::LazyCachingCredentialsProvider, RetryConfig};
+use aws_types::credentials::{
+    future, Credentials, CredentialsError, ProvideCredentials, SharedCredentialsProvider,
+};
+use http::{header, Method, Request, Uri};
+use itertools::Itertools;
+use log::info;
+use serde::Deserialize;
+
+use crate::client::{ClientConfig, ClientOptions};
+use crate::signing;
+use crate::utils::load_account_id;
+
+const SESSION_NAME: &str = "aws-sg-cleanup";
+
+/// Organizations is a global service signed in us-east-1. `aws-sdk-organizations` is not a
+/// dependency of this crate for the single call it makes, so the request is signed and
+/// sent directly.
+const ORGANIZATIONS_SIGNING_REGION: &str = "us-east-1";
+const ORGANIZATIONS_ENDPOINT: &str = "https://organizations.us-east-1.amazonaws.com/";
+
+/// An account to scan and how to reach it.
+#[derive(Clone, Debug)]
+pub struct Account {
+    pub account_id: String,
+    /// Name of the account in the organization.
+    pub name: Option<String>,
+    pub options: ClientOptions,
+}
+
+#[derive(Clone, Debug)]
+pub struct AssumeRole {
+    pub role_arn: String,
+    pub external_id: Option<String>,
+}
+
+/// `options` with credentials of `role`, assumed with the credentials of `options` and
+/// refreshed before they expire.
+pub fn assume_role(options: &ClientOptions, role: AssumeRole) -> ClientOptions {
+    let provider = LazyCachingCredentialsProvider::builder()
+        .load(AssumedRoleCredentials {
+            options: options.clone(),
+            role,
+        })
+        .build();
+    ClientOptions {
+        credentials: Some(SharedCredentialsProvider::new(provider)),
+        ..options.clone()
+    }
+}
+
+/// Calls STS through [`ClientConfig`] rather than `aws_config::sts::AssumeRoleProvider`
+/// so the endpoint overrides apply.
+#[derive(Debug)]
+struct AssumedRoleCredentials {
+    options: ClientOptions,
+    role: AssumeRole,
+}
+
+impl AssumedRoleCredentials {
+    async fn assume(&self) -> aws_types::credentials::Result {
+        let config = ClientConfig::load(
+            self.options.default_region(),
+            &self.options,
+            RetryConfig::new(),
+        )
+        .await;
+        let response = config
+            .sts()
+            .assume_role()
+            .role_arn(&self.role.role_arn)
+            .set_external_id(self.role.external_id.clone())
+            .role_session_name(SESSION_NAME)
+            .send()
+            .await
+            .map_err(CredentialsError::provider_error)?;
+        let credentials = response.credentials().ok_or_else(|| {
+            CredentialsError::provider_error("AssumeRole returned no credentials")
+        })?;
+        Ok(Credentials::new(
+            credentials.access_key_id().unwrap_or_default(),
+            credentials.secret_access_key().unwrap_or_default(),
+            credentials.session_token().map(ToOwned::to_owned),
+            credentials
+                .expiration()
+                .and_then(|x| SystemTime::try_from(*x).ok()),
+            "AssumeRole",
+        ))
+    }
+}
+
+impl ProvideCredentials for AssumedRoleCredentials {
+    fn provide_credentials<'a>(&'a self) -> future::ProvideCredentials<'a>
+    where
+        Self: 'a,
+    {
+        future::ProvideCredentials::new(self.assume())
+    }
+}
+
+/// The account of `options` or, with `organization_role`, every active account of the
+/// organization, reached by assuming that role. The calling account is reached directly.
+pub async fn resolve_accounts(
+    options: &ClientOptions,
+    organization_role: Option<&str>,
+) -> anyhow::Result<Vec<Account>> {
+    let caller = load_account_id(options).await?;
+    let Some(role_name) = organization_role else {
+        return Ok(vec![Account {
+            account_id: caller,
+            name: None,
+            options: options.clone(),
+        }]);
+    };
+
+    let accounts = list_organization_accounts(options).await?;
+    info!("found {} accounts in the organization", accounts.len());
+    Ok(accounts
+        .into_iter()
+        .filter(|account| account.status == "ACTIVE")
+        .map(|account| {
+            let options = if account.id == caller {
+                options.clone()
+            } else {
+                let partition = account.arn.split(':').nth(1).unwrap_or("aws");
+                let role = AssumeRole {
+                    role_arn: format!("arn:{}:iam::{}:role/{}", partition, account.id, role_name),
+                    external_id: None,
+                };
+                assume_role(options, role)
+            };
+            Account {
+                account_id: account.id,
+                name: Some(account.name),
+                options,
+            }
+        })
+        .collect_vec())
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct OrganizationAccount {
+    id: String,
+    arn: String,
+    name: String,
+    status: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ListAccountsResponse {
+    accounts: Vec<OrganizationAccount>,
+    next_token: Option<String>,
+}
+
+async fn list_organization_accounts(
+    options: &ClientOptions,
+) -> anyhow::Result<Vec<OrganizationAccount>> {
+    let config = ClientConfig::load(options.default_region(), options, RetryConfig::new()).await;
+    let uri = match config.endpoints().uri("organizations") {
+        Some(uri) => uri.clone(),
+        None => Uri::from_static(ORGANIZATIONS_ENDPOINT),
+    };
+
+    collect_pages(|next_token| {
+        let (config, uri) = (&config, uri.clone());
+        async move {
+            let body = match &next_token {
+                Some(token) => serde_json::json!({ "NextToken": token }),
+                None => serde_json::json!({}),
+            };
+            let request = Request::builder()
+                .method(Method::POST)
+                .uri(uri)
+                .header(header::CONTENT_TYPE, "application/x-amz-json-1.1")
+                .header("x-amz-target", "AWSOrganizationsV20161128.ListAccounts")
+                .body(body.to_string())?;
+            let (status, text) = signing::send(
+                config,
+                "organizations",
+                ORGANIZATIONS_SIGNING_REGION,
+                request,
+            )
+            .await?;
+            if !status.is_success() {
+                let error: serde_json::Value = serde_json::from_str(&text).unwrap_or_default();
+                bail!(
+                    "organizations:ListAccounts failed with {}: {} {}",
+                    status,
+                    error["__type"].as_str().unwrap_or("UnknownError"),
+                    error["Message"]
+                        .as_str()
+                        .or_else(|| error["message"].as_str())
+                        .unwrap_or_default()
+                );
+            }
+            Ok(text)
+        }
+    })
+    .await
+}
+
+/// Requests `ListAccounts` pages with `fetch`, passing the `NextToken` of the previous
+/// page, until a page has none.
+async fn collect_pages<F, Fut>(mut fetch: F) -> anyhow::Result<Vec<OrganizationAccount>>
+where
+    F: FnMut(Option<String>) -> Fut,
+    Fut: Future<Output = anyhow::Result<String>>,
+{
+    let mut accounts = vec![];
+    let mut next_token: Option<String> = None;
+    loop {
+        let text = fetch(next_token.take()).await?;
+        let response: ListAccountsResponse =
+            serde_json::from_str(&text).context("invalid ListAccounts response")?;
+        accounts.extend(response.accounts);
+        next_token = response.next_token;
+        if next_token.is_none() {
+            return Ok(accounts);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{collect_pages, ListAccountsResponse};
+
+    #[test]
+    fn test_list_accounts_response() {
+        let response: ListAccountsResponse = serde_json::from_str(
+            r#"{"Accounts": [{"Id": "111111111111", "Arn": "arn:aws:organizations::111111111111:account/o-1/111111111111",
+                "Email": "a@example.com", "Name": "prod", "Status": "ACTIVE", "JoinedMethod": "CREATED"}],
+                "NextToken": "t"}"#,
+        )
+        .unwrap();
+        assert_eq!(response.accounts[0].id, "111111111111");
+        assert_eq!(response.accounts[0].name, "prod");
+        assert_eq!(response.next_token.as_deref(), Some("t"));
+    }
+
+    #[tokio::test]
+    async fn test_collect_pages() {
+        let account = |id: &str| {
+            format!(
+                r#"{{"Id": "{id}", "Arn": "arn:aws:organizations::111111111111:account/o-1/{id}",
+                    "Name": "account {id}", "Status": "ACTIVE"}}"#
+            )
+        };
+        let pages = [
+            format!(
+                r#"{{"Accounts": [{}], "NextToken": "a+b/c="}}"#,
+                account("1")
+            ),
+            r#"{"Accounts": [], "NextToken": "d"}"#.to_string(),
+            format!(
+                r#"{{"Accounts": [{}, {}], "NextToken": null}}"#,
+                account("2"),
+                account("3")
+            ),
+        ];
+        let mut tokens = vec![];
+        let accounts = collect_pages(|token| {
+            tokens.push(token);
+            let page = pages[tokens.len() - 1].clone();
+            async move { Ok(page) }
+        })
+        .await
+        .unwrap();
+        assert_eq!(
+            accounts.iter().map(|x| x.id.as_str()).collect::<Vec<_>>(),
+            ["1", "2", "3"]
+        );
+        assert_eq!(
+            tokens,
+            [None, Some("a+b/c=".to_string()), Some("d".to_string())]
+        );
+    }
+}
@@ -1,38 +1,41 @@
-use aws_sdk_elasticloadbalancingv2::Client;
-use aws_types::SdkConfig;
-use futures::StreamExt;
-use itertools::Itertools;
-
-use crate::security::{SecurityGroups, SecurityGroupsProvider};
+use crate::backend::Backend;
+use crate::error::ProviderError;
+use crate::provider::SecurityGroupsProvider;
+use crate::security::SecurityGroups;
 
 pub struct ALBGroups {}
 
 #[async_trait::async_trait]
-impl SecurityGroupsProvider<SdkConfig> for ALBGroups {
-    async fn load(config: &SdkConfig) -> SecurityGroups {
-        let client = Client::new(config);
-        let group_ids = client
+impl SecurityGroupsProvider for ALBGroups {
+    fn name(&self) -> &'static str {
+        "alb"
+    }
+
+    fn description(&self) -> &'static str {
+        "Application and network load balancers"
+    }
+
+    fn iam_actions(&self) -> &'static [&'static str] {
+        &["elasticloadbalancing:DescribeLoadBalancers"]
+    }
+
+    async fn load(&self, backend: &dyn Backend) -> Result<SecurityGroups, ProviderError> {
+        let region = backend.region();
+        let load_balancers = backend
             .describe_load_balancers()
-            .into_paginator()
-            .items()
-            .send()
-            .flat_map(|res| {
-                let load_balancer = res.unwrap();
-                futures::stream::iter(
-                    load_balancer
-                        .security_groups()
-                        .unwrap_or_default()
-                        .iter()
-                        .map(|group| group.to_owned())
-                        .collect_vec(),
-                )
-            })
-            .collect::<Vec<_>>()
-            .await;
+            .await
+            .map_err(|err| ProviderError::new(self.name(), &region, err))?;
+
+        let resources = load_balancers.into_iter().map(|load_balancer| {
+            (
+                format!("load balancer {}", load_balancer.load_balancer_name),
+                load_balancer.security_groups,
+            )
+        });
 
-        SecurityGroups::create_from_group_ids(
-            format!("alb@{}", config.region().unwrap()),
-            group_ids.into_iter(),
-        )
+        Ok(SecurityGroups::create_from_resources(
+            format!("{}@{}", self.name(), region),
+            resources,
+        ))
     }
 }
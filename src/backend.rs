@@ -0,0 +1,560 @@
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use aws_sdk_ec2::model::{IpPermission, UserIdGroupPair};
+use aws_smithy_http::result::SdkError;
+use aws_smithy_types::retry::ProvideErrorKind;
+use futures::{Stream, TryStreamExt};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::client::ClientConfig;
+use crate::error::BackendError;
+use crate::security::{Rule, RulePeer};
+use crate::throttle::Throttle;
+
+/// The AWS calls made while scanning and cleaning one region, so providers and the
+/// cleanup can run against [`AwsBackend`] or an in-memory fake.
+#[async_trait]
+pub trait Backend: Send + Sync {
+    fn region(&self) -> String;
+
+    async fn describe_security_groups(&self) -> Result<Vec<SecurityGroup>, BackendError>;
+    async fn describe_security_group(
+        &self,
+        group_id: &str,
+    ) -> Result<Option<SecurityGroup>, BackendError>;
+    async fn describe_instances(&self) -> Result<Vec<Instance>, BackendError>;
+    async fn describe_network_interfaces(&self) -> Result<Vec<NetworkInterface>, BackendError>;
+
+    // Calls of services behind optional features, unsupported unless implemented.
+    async fn describe_load_balancers(&self) -> Result<Vec<LoadBalancer>, BackendError> {
+        Err(unsupported("describe_load_balancers"))
+    }
+    async fn describe_cache_clusters(&self) -> Result<Vec<CacheCluster>, BackendError> {
+        Err(unsupported("describe_cache_clusters"))
+    }
+    async fn list_functions(&self) -> Result<Vec<Function>, BackendError> {
+        Err(unsupported("list_functions"))
+    }
+    async fn describe_db_instances(&self) -> Result<Vec<DbInstance>, BackendError> {
+        Err(unsupported("describe_db_instances"))
+    }
+    async fn describe_db_clusters(&self) -> Result<Vec<DbCluster>, BackendError> {
+        Err(unsupported("describe_db_clusters"))
+    }
+
+    async fn revoke_ingress(&self, group_id: &str, rules: &[Rule]) -> Result<(), BackendError>;
+    async fn revoke_egress(&self, group_id: &str, rules: &[Rule]) -> Result<(), BackendError>;
+    async fn delete_security_group(&self, group_id: &str) -> Result<(), BackendError>;
+}
+
+fn unsupported(call: &str) -> BackendError {
+    BackendError::Service(format!("{} is not supported by this backend", call).into())
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SecurityGroup {
+    pub group_id: String,
+    pub vpc_id: Option<String>,
+    pub owner_id: Option<String>,
+    pub group_name: String,
+    pub description: String,
+    pub tags: BTreeMap<String, String>,
+    pub ingress: Vec<Rule>,
+    pub egress: Vec<Rule>,
+}
+
+/// An EC2 instance, with the EC2-Classic groups of its reservation included.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Instance {
+    pub instance_id: String,
+    pub security_groups: Vec<String>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NetworkInterface {
+    pub network_interface_id: String,
+    pub security_groups: Vec<String>,
+    /// Set by the service that created the interface, such as an ECS task attachment.
+    pub description: Option<String>,
+    pub interface_type: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LoadBalancer {
+    pub load_balancer_name: String,
+    pub security_groups: Vec<String>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CacheCluster {
+    pub cache_cluster_id: String,
+    pub security_groups: Vec<String>,
+    /// Cache security groups, referenced by name.
+    pub cache_security_groups: Vec<String>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Function {
+    pub function_name: String,
+    pub security_groups: Vec<String>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DbInstance {
+    pub db_instance_identifier: String,
+    /// VPC of the DB subnet group.
+    pub vpc_id: Option<String>,
+    pub security_groups: Vec<String>,
+    /// DB security groups, referenced by name.
+    pub db_security_groups: Vec<String>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DbCluster {
+    pub db_cluster_identifier: String,
+    pub security_groups: Vec<String>,
+}
+
+/// [`Backend`] calling the AWS APIs of one region.
+pub struct AwsBackend {
+    region: String,
+    throttle: Option<Arc<Throttle>>,
+    ec2: aws_sdk_ec2::Client,
+    #[cfg(feature = "alb")]
+    elbv2: aws_sdk_elasticloadbalancingv2::Client,
+    #[cfg(feature = "elasticache")]
+    elasticache: aws_sdk_elasticache::Client,
+    #[cfg(feature = "lambda")]
+    lambda: aws_sdk_lambda::Client,
+    #[cfg(feature = "rds")]
+    rds: aws_sdk_rds::Client,
+}
+
+impl AwsBackend {
+    pub fn new(config: &ClientConfig) -> Self {
+        Self {
+            region: config.region(),
+            throttle: None,
+            ec2: config.ec2(),
+            #[cfg(feature = "alb")]
+            elbv2: config.elbv2(),
+            #[cfg(feature = "elasticache")]
+            elasticache: config.elasticache(),
+            #[cfg(feature = "lambda")]
+            lambda: config.lambda(),
+            #[cfg(feature = "rds")]
+            rds: config.rds(),
+        }
+    }
+
+    /// Paces every request, each page of a listing included, with `throttle`, slowing
+    /// down when AWS still throttles a request after the SDK retries.
+    pub fn with_throttle(mut self, throttle: Arc<Throttle>) -> Self {
+        self.throttle = Some(throttle);
+        self
+    }
+
+    /// Sends one request in its slot of `service`.
+    async fn send<T, E>(
+        &self,
+        service: &str,
+        request: impl Future<Output = Result<T, SdkError<E>>>,
+    ) -> Result<T, BackendError>
+    where
+        E: ProvideErrorKind + std::error::Error + Send + Sync + 'static,
+    {
+        let Some(throttle) = &self.throttle else {
+            return Ok(request.await?);
+        };
+        throttle.pace(service, &self.region).await;
+        let res = request.await.map_err(BackendError::from);
+        match &res {
+            Ok(_) => throttle.speed_up(service, &self.region),
+            Err(BackendError::Throttled(_)) => throttle.slow_down(service, &self.region),
+            Err(_) => {}
+        }
+        res
+    }
+
+    /// Collects every page of a paginator, each request in its own slot of `service`.
+    async fn pages<T, E>(
+        &self,
+        service: &str,
+        pages: impl Stream<Item = Result<T, SdkError<E>>>,
+    ) -> Result<Vec<T>, BackendError>
+    where
+        E: ProvideErrorKind + std::error::Error + Send + Sync + 'static,
+    {
+        let mut pages = std::pin::pin!(pages);
+        let mut items = vec![];
+        while let Some(page) = self.send(service, pages.try_next()).await? {
+            items.push(page);
+        }
+        Ok(items)
+    }
+}
+
+#[cfg(any(feature = "alb", feature = "lambda"))]
+fn strings(items: Option<&[String]>) -> Vec<String> {
+    items.unwrap_or_default().to_vec()
+}
+
+fn security_group(group: aws_sdk_ec2::model::SecurityGroup) -> Option<SecurityGroup> {
+    let aws_sdk_ec2::model::SecurityGroup {
+        group_id,
+        vpc_id,
+        owner_id,
+        group_name,
+        description,
+        ip_permissions,
+        ip_permissions_egress,
+        tags,
+        ..
+    } = group;
+    Some(SecurityGroup {
+        group_id: group_id?,
+        vpc_id,
+        owner_id,
+        group_name: group_name.unwrap_or_default(),
+        description: description.unwrap_or_default(),
+        tags: tags
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|tag| Some((tag.key?, tag.value.unwrap_or_default())))
+            .collect(),
+        ingress: Rule::from_permissions(ip_permissions.unwrap_or_default()),
+        egress: Rule::from_permissions(ip_permissions_egress.unwrap_or_default()),
+    })
+}
+
+/// Group rules as permissions for a revoke call. Only group peers can be revoked this
+/// way, other rules are skipped.
+fn group_permissions(rules: &[Rule]) -> Vec<IpPermission> {
+    rules
+        .iter()
+        .filter_map(|rule| match &rule.peer {
+            RulePeer::Group { group_id, user_id } => Some(
+                IpPermission::builder()
+                    .ip_protocol(&rule.protocol)
+                    .set_from_port(rule.from_port)
+                    .set_to_port(rule.to_port)
+                    .user_id_group_pairs(
+                        UserIdGroupPair::builder()
+                            .group_id(group_id)
+                            .set_user_id(user_id.clone())
+                            .build(),
+                    )
+                    .build(),
+            ),
+            _ => None,
+        })
+        .collect()
+}
+
+#[async_trait]
+impl Backend for AwsBackend {
+    fn region(&self) -> String {
+        self.region.clone()
+    }
+
+    async fn describe_security_groups(&self) -> Result<Vec<SecurityGroup>, BackendError> {
+        let groups = self
+            .pages(
+                "ec2",
+                self.ec2.describe_security_groups().into_paginator().send(),
+            )
+            .await?
+            .into_iter()
+            .flat_map(|page| page.security_groups.unwrap_or_default())
+            .collect_vec();
+        Ok(groups.into_iter().filter_map(security_group).collect())
+    }
+
+    /// Asking for a group by ID fails with `InvalidGroup.NotFound` rather than returning
+    /// no groups once it is gone, which is mapped to `None`.
+    async fn describe_security_group(
+        &self,
+        group_id: &str,
+    ) -> Result<Option<SecurityGroup>, BackendError> {
+        let request = async {
+            let res = self
+                .ec2
+                .describe_security_groups()
+                .group_ids(group_id)
+                .send()
+                .await;
+            match res {
+                Err(SdkError::ServiceError { err, .. })
+                    if err.code() == Some("InvalidGroup.NotFound") =>
+                {
+                    Ok(None)
+                }
+                res => res.map(Some),
+            }
+        };
+        let Some(response) = self.send("ec2", request).await? else {
+            return Ok(None);
+        };
+        Ok(response
+            .security_groups()
+            .and_then(|groups| groups.first())
+            .cloned()
+            .and_then(security_group))
+    }
+
+    async fn describe_instances(&self) -> Result<Vec<Instance>, BackendError> {
+        let reservations = self
+            .pages("ec2", self.ec2.describe_instances().into_paginator().send())
+            .await?
+            .into_iter()
+            .flat_map(|page| page.reservations.unwrap_or_default())
+            .collect_vec();
+        Ok(reservations
+            .iter()
+            .flat_map(|reservation| {
+                let classic = reservation
+                    .groups()
+                    .unwrap_or_default()
+                    .iter()
+                    .filter_map(|group| group.group_id())
+                    .map(ToOwned::to_owned)
+                    .collect_vec();
+                reservation
+                    .instances()
+                    .unwrap_or_default()
+                    .iter()
+                    .map(move |instance| Instance {
+                        instance_id: instance.instance_id().unwrap_or_default().to_owned(),
+                        security_groups: instance
+                            .security_groups()
+                            .unwrap_or_default()
+                            .iter()
+                            .filter_map(|group| group.group_id())
+                            .map(ToOwned::to_owned)
+                            .chain(classic.clone())
+                            .collect(),
+                    })
+            })
+            .collect())
+    }
+
+    async fn describe_network_interfaces(&self) -> Result<Vec<NetworkInterface>, BackendError> {
+        let network_interfaces = self
+            .pages(
+                "ec2",
+                self.ec2
+                    .describe_network_interfaces()
+                    .into_paginator()
+                    .send(),
+            )
+            .await?
+            .into_iter()
+            .flat_map(|page| page.network_interfaces.unwrap_or_default())
+            .collect_vec();
+        Ok(network_interfaces
+            .iter()
+            .map(|eni| NetworkInterface {
+                network_interface_id: eni.network_interface_id().unwrap_or_default().to_owned(),
+                security_groups: eni
+                    .groups()
+                    .unwrap_or_default()
+                    .iter()
+                    .filter_map(|group| group.group_id())
+                    .map(ToOwned::to_owned)
+                    .collect(),
+                description: eni
+                    .description()
+                    .filter(|x| !x.is_empty())
+                    .map(ToOwned::to_owned),
+                interface_type: eni.interface_type().map(|x| x.as_str().to_owned()),
+            })
+            .collect())
+    }
+
+    #[cfg(feature = "alb")]
+    async fn describe_load_balancers(&self) -> Result<Vec<LoadBalancer>, BackendError> {
+        let load_balancers = self
+            .pages(
+                "elasticloadbalancing",
+                self.elbv2.describe_load_balancers().into_paginator().send(),
+            )
+            .await?
+            .into_iter()
+            .flat_map(|page| page.load_balancers.unwrap_or_default())
+            .collect_vec();
+        Ok(load_balancers
+            .iter()
+            .map(|load_balancer| LoadBalancer {
+                load_balancer_name: load_balancer
+                    .load_balancer_name()
+                    .unwrap_or_default()
+                    .to_owned(),
+                security_groups: strings(load_balancer.security_groups()),
+            })
+            .collect())
+    }
+
+    #[cfg(feature = "elasticache")]
+    async fn describe_cache_clusters(&self) -> Result<Vec<CacheCluster>, BackendError> {
+        let clusters = self
+            .pages(
+                "elasticache",
+                self.elasticache
+                    .describe_cache_clusters()
+                    .into_paginator()
+                    .send(),
+            )
+            .await?
+            .into_iter()
+            .flat_map(|page| page.cache_clusters.unwrap_or_default())
+            .collect_vec();
+        Ok(clusters
+            .iter()
+            .map(|cluster| CacheCluster {
+                cache_cluster_id: cluster.cache_cluster_id().unwrap_or_default().to_owned(),
+                security_groups: cluster
+                    .security_groups()
+                    .unwrap_or_default()
+                    .iter()
+                    .filter_map(|x| x.security_group_id())
+                    .map(ToOwned::to_owned)
+                    .collect(),
+                cache_security_groups: cluster
+                    .cache_security_groups()
+                    .unwrap_or_default()
+                    .iter()
+                    .filter_map(|x| x.cache_security_group_name())
+                    .map(ToOwned::to_owned)
+                    .collect(),
+            })
+            .collect())
+    }
+
+    #[cfg(feature = "lambda")]
+    async fn list_functions(&self) -> Result<Vec<Function>, BackendError> {
+        let functions = self
+            .pages(
+                "lambda",
+                self.lambda.list_functions().into_paginator().send(),
+            )
+            .await?
+            .into_iter()
+            .flat_map(|page| page.functions.unwrap_or_default())
+            .collect_vec();
+        Ok(functions
+            .iter()
+            .map(|function| Function {
+                function_name: function.function_name().unwrap_or_default().to_owned(),
+                security_groups: strings(
+                    function
+                        .vpc_config()
+                        .and_then(|vpc_config| vpc_config.security_group_ids()),
+                ),
+            })
+            .collect())
+    }
+
+    #[cfg(feature = "rds")]
+    async fn describe_db_instances(&self) -> Result<Vec<DbInstance>, BackendError> {
+        let instances = self
+            .pages(
+                "rds",
+                self.rds.describe_db_instances().into_paginator().send(),
+            )
+            .await?
+            .into_iter()
+            .flat_map(|page| page.db_instances.unwrap_or_default())
+            .collect_vec();
+        Ok(instances
+            .iter()
+            .map(|db| DbInstance {
+                db_instance_identifier: db.db_instance_identifier().unwrap_or_default().to_owned(),
+                vpc_id: db
+                    .db_subnet_group()
+                    .and_then(|subnet_group| subnet_group.vpc_id())
+                    .map(ToOwned::to_owned),
+                security_groups: db
+                    .vpc_security_groups()
+                    .unwrap_or_default()
+                    .iter()
+                    .filter_map(|group| group.vpc_security_group_id())
+                    .map(ToOwned::to_owned)
+                    .collect(),
+                db_security_groups: db
+                    .db_security_groups()
+                    .unwrap_or_default()
+                    .iter()
+                    .filter_map(|group| group.db_security_group_name())
+                    .map(ToOwned::to_owned)
+                    .collect(),
+            })
+            .collect())
+    }
+
+    #[cfg(feature = "rds")]
+    async fn describe_db_clusters(&self) -> Result<Vec<DbCluster>, BackendError> {
+        let clusters = self
+            .pages(
+                "rds",
+                self.rds.describe_db_clusters().into_paginator().send(),
+            )
+            .await?
+            .into_iter()
+            .flat_map(|page| page.db_clusters.unwrap_or_default())
+            .collect_vec();
+        Ok(clusters
+            .iter()
+            .map(|cluster| DbCluster {
+                db_cluster_identifier: cluster
+                    .db_cluster_identifier()
+                    .unwrap_or_default()
+                    .to_owned(),
+                security_groups: cluster
+                    .vpc_security_groups()
+                    .unwrap_or_default()
+                    .iter()
+                    .filter_map(|group| group.vpc_security_group_id())
+                    .map(ToOwned::to_owned)
+                    .collect(),
+            })
+            .collect())
+    }
+
+    async fn revoke_ingress(&self, group_id: &str, rules: &[Rule]) -> Result<(), BackendError> {
+        let request = self
+            .ec2
+            .revoke_security_group_ingress()
+            .group_id(group_id)
+            .set_ip_permissions(Some(group_permissions(rules)));
+        self.send("ec2", request.send()).await?;
+        Ok(())
+    }
+
+    async fn revoke_egress(&self, group_id: &str, rules: &[Rule]) -> Result<(), BackendError> {
+        let request = self
+            .ec2
+            .revoke_security_group_egress()
+            .group_id(group_id)
+            .set_ip_permissions(Some(group_permissions(rules)));
+        self.send("ec2", request.send()).await?;
+        Ok(())
+    }
+
+    async fn delete_security_group(&self, group_id: &str) -> Result<(), BackendError> {
+        let request = self.ec2.delete_security_group().group_id(group_id);
+        self.send("ec2", request.send()).await?;
+        Ok(())
+    }
+}
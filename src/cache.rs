@@ -0,0 +1,142 @@
+use std::{
+    fs::{self, File},
+    io::BufReader,
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::security::SecurityGroups;
+
+/// On-disk cache of provider results, keyed by account, region and provider.
+pub struct ResponseCache {
+    dir: PathBuf,
+    ttl: Duration,
+    refresh: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    /// Seconds since the Unix epoch.
+    cached_at: u64,
+    groups: SecurityGroups,
+}
+
+impl ResponseCache {
+    /// With `refresh` set, every lookup misses but fresh results are still written.
+    pub fn new(dir: PathBuf, ttl: Duration, refresh: bool) -> Self {
+        Self { dir, ttl, refresh }
+    }
+
+    pub fn default_dir() -> PathBuf {
+        std::env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+            .unwrap_or_else(std::env::temp_dir)
+            .join("aws-sg-cleanup")
+    }
+
+    pub fn get(&self, account: &str, region: &str, provider: &str) -> Option<SecurityGroups> {
+        if self.refresh {
+            return None;
+        }
+        let path = self.path(account, region, provider);
+        let entry: Entry = File::open(&path)
+            .ok()
+            .and_then(|file| serde_json::from_reader(BufReader::new(file)).ok())?;
+        let age = now().saturating_sub(entry.cached_at);
+        if age > self.ttl.as_secs() {
+            return None;
+        }
+        debug!("{} cached {}s ago", path.display(), age);
+        Some(entry.groups)
+    }
+
+    /// Failing to write the cache never fails the scan.
+    pub fn put(&self, account: &str, region: &str, provider: &str, groups: &SecurityGroups) {
+        if let Err(err) = self.try_put(account, region, provider, groups) {
+            warn!(
+                "failed to cache {}@{} of {}: {:#}",
+                provider, region, account, err
+            );
+        }
+    }
+
+    fn try_put(
+        &self,
+        account: &str,
+        region: &str,
+        provider: &str,
+        groups: &SecurityGroups,
+    ) -> anyhow::Result<()> {
+        let path = self.path(account, region, provider);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("failed to create {}", parent.display()))?;
+        }
+        let entry = Entry {
+            cached_at: now(),
+            groups: groups.clone(),
+        };
+        let file =
+            File::create(&path).with_context(|| format!("failed to create {}", path.display()))?;
+        serde_json::to_writer(file, &entry)?;
+        Ok(())
+    }
+
+    fn path(&self, account: &str, region: &str, provider: &str) -> PathBuf {
+        self.dir
+            .join(account)
+            .join(region)
+            .join(format!("{}.json", provider))
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|x| x.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use maplit::hashmap;
+
+    use super::ResponseCache;
+    use crate::security::SecurityGroups;
+
+    #[test]
+    fn test_get_put() {
+        let dir = std::env::temp_dir().join(format!("aws-sg-cleanup-test-{}", std::process::id()));
+        let groups = SecurityGroups {
+            external_references: hashmap![
+                "sg-1".to_string() => vec!["ec2@eu-west-1".into()],
+            ],
+            ..SecurityGroups::default()
+        };
+
+        let cache = ResponseCache::new(dir.clone(), Duration::from_secs(60), false);
+        assert!(cache.get("123", "eu-west-1", "ec2").is_none());
+        cache.put("123", "eu-west-1", "ec2", &groups);
+        assert_eq!(
+            cache
+                .get("123", "eu-west-1", "ec2")
+                .unwrap()
+                .external_references,
+            groups.external_references
+        );
+        assert!(cache.get("123", "us-east-1", "ec2").is_none());
+        assert!(cache.get("456", "eu-west-1", "ec2").is_none());
+
+        let refresh = ResponseCache::new(dir.clone(), Duration::from_secs(60), true);
+        assert!(refresh.get("123", "eu-west-1", "ec2").is_none());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
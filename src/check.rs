@@ -0,0 +1,259 @@
+//! Thresholds on the number of unused groups, for scheduled pipelines. A check only
+//! reads the inventory and never deletes anything.
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::Path;
+
+use anyhow::Context;
+
+use crate::security::{ExistingGroup, SecurityGroups};
+
+/// Maximum number of unused groups, in total and optionally per region.
+#[derive(Clone, Debug, Default)]
+pub struct Thresholds {
+    pub max_unused: usize,
+    /// Limits for single regions, applied to the region in every account.
+    pub regions: HashMap<String, usize>,
+}
+
+/// Groups that are expected to be unused and do not count, by ID or name.
+#[derive(Clone, Debug, Default)]
+pub struct Allowlist {
+    entries: HashSet<String>,
+}
+
+impl Allowlist {
+    pub fn new(entries: impl IntoIterator<Item = String>) -> Self {
+        Self {
+            entries: entries.into_iter().collect(),
+        }
+    }
+
+    /// Adds the entries of a file with one group ID or name per line. Empty lines and
+    /// lines starting with `#` are ignored.
+    pub fn extend_from_file(&mut self, path: &Path) -> anyhow::Result<()> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        self.entries.extend(
+            text.lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(ToOwned::to_owned),
+        );
+        Ok(())
+    }
+
+    pub fn contains(&self, group: &ExistingGroup) -> bool {
+        self.entries.contains(&group.group_id) || self.entries.contains(&group.group_name)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Ok,
+    ThresholdExceeded,
+    /// No threshold was exceeded, but some regions or accounts could not be checked.
+    ScanIncomplete,
+}
+
+impl Outcome {
+    /// `1` is left to errors that stop the check altogether.
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Self::Ok => 0,
+            Self::ThresholdExceeded => 2,
+            Self::ScanIncomplete => 3,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RegionCheck<'a> {
+    pub account_id: &'a str,
+    pub region: &'a str,
+    /// Unused groups that count towards the thresholds.
+    pub unused: Vec<&'a ExistingGroup>,
+    /// Unused groups on the allowlist.
+    pub allowed: usize,
+    pub max_unused: Option<usize>,
+    /// Incomplete regions are reported but not counted, as their unused groups may
+    /// be used by a service that failed to load.
+    pub complete: bool,
+}
+
+impl RegionCheck<'_> {
+    pub fn exceeded(&self) -> bool {
+        self.complete
+            && self
+                .max_unused
+                .is_some_and(|max_unused| self.unused.len() > max_unused)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CheckResult<'a> {
+    /// Every scanned region, ordered by account and region.
+    pub regions: Vec<RegionCheck<'a>>,
+    /// Unused groups in complete regions.
+    pub total: usize,
+    pub max_unused: usize,
+    pub failed_accounts: Vec<String>,
+}
+
+impl CheckResult<'_> {
+    /// A threshold exceeded in complete regions is certain, so it wins over gaps in
+    /// the scan.
+    pub fn outcome(&self) -> Outcome {
+        if self.total > self.max_unused || self.regions.iter().any(RegionCheck::exceeded) {
+            Outcome::ThresholdExceeded
+        } else if !self.failed_accounts.is_empty() || self.regions.iter().any(|x| !x.complete) {
+            Outcome::ScanIncomplete
+        } else {
+            Outcome::Ok
+        }
+    }
+}
+
+/// Counts the unused groups of every scanned region of `groups` against `thresholds`.
+/// `failed_accounts` are accounts the scan could not reach.
+pub fn check<'a>(
+    groups: &'a SecurityGroups,
+    thresholds: &Thresholds,
+    allowlist: &Allowlist,
+    failed_accounts: &[String],
+) -> CheckResult<'a> {
+    let mut unused: HashMap<(&str, &str), Vec<&ExistingGroup>> = HashMap::new();
+    for group in groups.find_unused() {
+        unused
+            .entry((&group.account_id, &group.region))
+            .or_default()
+            .push(group);
+    }
+    let scanned: BTreeSet<(&str, &str)> = groups
+        .coverage
+        .iter()
+        .flat_map(|(account_id, regions)| {
+            regions
+                .keys()
+                .map(move |region| (account_id.as_str(), region.as_str()))
+        })
+        .chain(unused.keys().copied())
+        .collect();
+
+    let regions: Vec<RegionCheck> = scanned
+        .into_iter()
+        .map(|(account_id, region)| {
+            let (allowed, counted): (Vec<&ExistingGroup>, Vec<&ExistingGroup>) = unused
+                .remove(&(account_id, region))
+                .unwrap_or_default()
+                .into_iter()
+                .partition(|group| allowlist.contains(group));
+            RegionCheck {
+                account_id,
+                region,
+                unused: counted,
+                allowed: allowed.len(),
+                max_unused: thresholds.regions.get(region).copied(),
+                complete: groups.is_region_complete(account_id, region),
+            }
+        })
+        .collect();
+    CheckResult {
+        total: regions
+            .iter()
+            .filter(|x| x.complete)
+            .map(|x| x.unused.len())
+            .sum(),
+        regions,
+        max_unused: thresholds.max_unused,
+        failed_accounts: failed_accounts.to_vec(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use maplit::hashmap;
+
+    use super::{check, Allowlist, Outcome, Thresholds};
+    use crate::security::{Coverage, ExistingGroup, SecurityGroups};
+
+    fn groups() -> SecurityGroups {
+        let group = |region: &str, group_id: &str| ExistingGroup {
+            account_id: "1".to_string(),
+            region: region.to_string(),
+            group_id: group_id.to_string(),
+            group_name: format!("{}-name", group_id),
+            ..ExistingGroup::default()
+        };
+        let mut groups = SecurityGroups {
+            existing_groups: vec![
+                group("eu-west-1", "sg-1"),
+                group("eu-west-1", "sg-2"),
+                group("eu-west-1", "sg-3"),
+                group("us-east-1", "sg-4"),
+            ],
+            ..SecurityGroups::default()
+        };
+        groups.record_coverage("1", "eu-west-1", "ec2", Coverage::Complete);
+        groups.record_coverage("1", "us-east-1", "ec2", Coverage::Complete);
+        groups.record_coverage("1", "eu-north-1", "ec2", Coverage::Complete);
+        groups
+    }
+
+    #[test]
+    fn test_thresholds() {
+        let groups = groups();
+        let thresholds = |max_unused: usize| Thresholds {
+            max_unused,
+            regions: hashmap!["eu-west-1".to_string() => 2],
+        };
+
+        let result = check(&groups, &thresholds(4), &Allowlist::default(), &[]);
+        assert_eq!(result.regions.len(), 3);
+        assert_eq!(result.regions[0].region, "eu-north-1");
+        assert_eq!(result.total, 4);
+        assert!(result.regions[1].exceeded());
+        assert_eq!(result.outcome(), Outcome::ThresholdExceeded);
+
+        let allowlist = Allowlist::new(["sg-1".to_string(), "sg-4-name".to_string()]);
+        let result = check(&groups, &thresholds(2), &allowlist, &[]);
+        assert_eq!(result.total, 2);
+        assert_eq!(result.regions[1].allowed, 1);
+        assert_eq!(result.outcome(), Outcome::Ok);
+        assert_eq!(result.outcome().exit_code(), 0);
+
+        let result = check(&groups, &thresholds(1), &allowlist, &[]);
+        assert_eq!(result.outcome().exit_code(), 2);
+    }
+
+    #[test]
+    fn test_incomplete() {
+        let mut groups = groups();
+        groups.record_coverage(
+            "1",
+            "eu-west-1",
+            "rds",
+            Coverage::Failed("denied".to_string()),
+        );
+        let thresholds = Thresholds {
+            max_unused: 1,
+            ..Thresholds::default()
+        };
+        let result = check(&groups, &thresholds, &Allowlist::default(), &[]);
+        assert_eq!(result.total, 1);
+        assert_eq!(result.outcome(), Outcome::ScanIncomplete);
+        assert_eq!(result.outcome().exit_code(), 3);
+
+        groups.record_coverage("1", "eu-west-1", "rds", Coverage::Complete);
+        let result = check(
+            &groups,
+            &Thresholds {
+                max_unused: 4,
+                ..Thresholds::default()
+            },
+            &Allowlist::default(),
+            &["2".to_string()],
+        );
+        assert_eq!(result.outcome(), Outcome::ScanIncomplete);
+    }
+}
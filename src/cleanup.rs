@@ -0,0 +1,85 @@
+use itertools::Itertools;
+use log::{info, warn};
+
+use crate::backend::Backend;
+use crate::error::BackendError;
+use crate::graph::DeletionStep;
+use crate::security::{Rule, RulePeer};
+
+/// Runs deletion steps for one region. Service errors such as a dependency violation
+/// are logged and skipped, transport errors abort the run.
+pub async fn execute(backend: &dyn Backend, steps: &[DeletionStep<'_>]) -> anyhow::Result<()> {
+    for step in steps {
+        match step {
+            DeletionStep::Revoke { group, referenced } => {
+                info!(
+                    "revoking rules of {} referencing {}",
+                    group.group_id,
+                    referenced.join(", ")
+                );
+                revoke_references(backend, &group.group_id, referenced).await?;
+            }
+            DeletionStep::Delete(group) => {
+                info!("deleting {}", group.group_id);
+                match backend.delete_security_group(&group.group_id).await {
+                    Err(err @ BackendError::Transport(_)) => return Err(err.into()),
+                    Err(err) => warn!("failed to delete {}: {}", group.group_id, err),
+                    Ok(_) => {}
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Revokes the ingress and egress rules of `group_id` that reference any of `referenced`,
+/// based on the current state of the group.
+async fn revoke_references(
+    backend: &dyn Backend,
+    group_id: &str,
+    referenced: &[&str],
+) -> anyhow::Result<()> {
+    let group = match backend.describe_security_group(group_id).await {
+        Err(err @ BackendError::Transport(_)) => return Err(err.into()),
+        Err(err) => {
+            warn!("failed to describe {}: {}", group_id, err);
+            return Ok(());
+        }
+        Ok(None) => {
+            warn!("{} no longer exists", group_id);
+            return Ok(());
+        }
+        Ok(Some(group)) => group,
+    };
+
+    let ingress = referencing_rules(group.ingress, referenced);
+    if !ingress.is_empty() {
+        match backend.revoke_ingress(group_id, &ingress).await {
+            Err(err @ BackendError::Transport(_)) => return Err(err.into()),
+            Err(err) => warn!("failed to revoke ingress rules of {}: {}", group_id, err),
+            Ok(_) => {}
+        }
+    }
+
+    let egress = referencing_rules(group.egress, referenced);
+    if !egress.is_empty() {
+        match backend.revoke_egress(group_id, &egress).await {
+            Err(err @ BackendError::Transport(_)) => return Err(err.into()),
+            Err(err) => warn!("failed to revoke egress rules of {}: {}", group_id, err),
+            Ok(_) => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Narrows `rules` down to the ones allowing any of `referenced`, keeping the protocol
+/// and port range so the revoke matches the existing rule exactly.
+fn referencing_rules(rules: Vec<Rule>, referenced: &[&str]) -> Vec<Rule> {
+    rules
+        .into_iter()
+        .filter(|rule| {
+            matches!(&rule.peer, RulePeer::Group { group_id, .. } if referenced.contains(&group_id.as_str()))
+        })
+        .collect_vec()
+}
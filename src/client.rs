@@ -0,0 +1,203 @@
+use std::collections::HashMap;
+
+use anyhow::{bail, Context};
+use aws_config::{
+    default_provider::{credentials::DefaultCredentialsChain, region::DefaultRegionChain},
+    meta::region::ProvideRegion,
+    RetryConfig,
+};
+use aws_smithy_http::endpoint::Endpoint;
+use aws_types::{credentials::SharedCredentialsProvider, SdkConfig};
+use http::Uri;
+
+/// Services with overridable endpoints, named like the `AWS_ENDPOINT_URL_<SERVICE>`
+/// environment variables.
+pub const SERVICES: &[&str] = &[
+    "ec2",
+    "elastic_load_balancing_v2",
+    "elasticache",
+    "iam",
+    "lambda",
+    "organizations",
+    "rds",
+    "sts",
+];
+
+/// Endpoint overrides, for example to run against LocalStack or moto.
+#[derive(Clone, Debug, Default)]
+pub struct Endpoints {
+    global: Option<Uri>,
+    services: HashMap<&'static str, Uri>,
+}
+
+impl Endpoints {
+    /// Reads `AWS_ENDPOINT_URL` and `AWS_ENDPOINT_URL_<SERVICE>`.
+    pub fn from_env() -> anyhow::Result<Self> {
+        let mut endpoints = Self::default();
+        if let Ok(url) = std::env::var("AWS_ENDPOINT_URL") {
+            endpoints.set_global(&url)?;
+        }
+        for service in SERVICES {
+            let var = format!("AWS_ENDPOINT_URL_{}", service.to_uppercase());
+            if let Ok(url) = std::env::var(&var) {
+                endpoints.set_service(service, &url).context(var)?;
+            }
+        }
+        Ok(endpoints)
+    }
+
+    pub fn set_global(&mut self, url: &str) -> anyhow::Result<()> {
+        self.global = Some(parse_url(url)?);
+        Ok(())
+    }
+
+    pub fn set_service(&mut self, service: &str, url: &str) -> anyhow::Result<()> {
+        let Some(service) = SERVICES.iter().find(|x| **x == service) else {
+            bail!(
+                "unknown service {}, expected one of {}",
+                service,
+                SERVICES.join(", ")
+            );
+        };
+        self.services.insert(service, parse_url(url)?);
+        Ok(())
+    }
+
+    /// The per-service override, falling back to the global one.
+    pub fn uri(&self, service: &str) -> Option<&Uri> {
+        self.services.get(service).or(self.global.as_ref())
+    }
+
+    pub fn resolve(&self, service: &str) -> Option<Endpoint> {
+        self.uri(service)
+            .map(|uri| Endpoint::immutable(uri.clone()))
+    }
+}
+
+fn parse_url(url: &str) -> anyhow::Result<Uri> {
+    url.parse()
+        .with_context(|| format!("invalid endpoint url {}", url))
+}
+
+/// How to reach AWS: endpoint overrides and credentials, the default provider chain
+/// when unset.
+#[derive(Clone, Debug, Default)]
+pub struct ClientOptions {
+    pub endpoints: Endpoints,
+    pub credentials: Option<SharedCredentialsProvider>,
+    /// Shared config profile for the default credentials and region.
+    pub profile: Option<String>,
+}
+
+impl ClientOptions {
+    /// Region for calls not tied to a scanned region.
+    pub fn default_region(&self) -> DefaultRegionChain {
+        let mut builder = DefaultRegionChain::builder();
+        if let Some(profile) = &self.profile {
+            builder = builder.profile_name(profile);
+        }
+        builder.build()
+    }
+}
+
+/// Shared SDK configuration for one region, building service clients with the
+/// endpoint overrides applied.
+#[derive(Clone, Debug)]
+pub struct ClientConfig {
+    sdk_config: SdkConfig,
+    endpoints: Endpoints,
+}
+
+macro_rules! client {
+    ($name:ident, $sdk:ident, $service:literal) => {
+        pub fn $name(&self) -> $sdk::Client {
+            let mut builder = $sdk::config::Builder::from(&self.sdk_config);
+            if let Some(endpoint) = self.endpoints.resolve($service) {
+                builder = builder.endpoint_resolver(endpoint);
+            }
+            $sdk::Client::from_conf(builder.build())
+        }
+    };
+}
+
+impl ClientConfig {
+    pub async fn load(
+        region: impl ProvideRegion + 'static,
+        options: &ClientOptions,
+        retry_config: RetryConfig,
+    ) -> Self {
+        let mut loader = aws_config::from_env()
+            .region(region)
+            .retry_config(retry_config);
+        if let Some(credentials) = &options.credentials {
+            loader = loader.credentials_provider(credentials.clone());
+        } else if let Some(profile) = &options.profile {
+            let credentials = DefaultCredentialsChain::builder()
+                .profile_name(profile)
+                .build()
+                .await;
+            loader = loader.credentials_provider(credentials);
+        }
+        Self {
+            sdk_config: loader.load().await,
+            endpoints: options.endpoints.clone(),
+        }
+    }
+
+    pub fn sdk_config(&self) -> &SdkConfig {
+        &self.sdk_config
+    }
+
+    pub fn endpoints(&self) -> &Endpoints {
+        &self.endpoints
+    }
+
+    pub fn region(&self) -> String {
+        self.sdk_config
+            .region()
+            .map(|region| region.to_string())
+            .unwrap_or_default()
+    }
+
+    client!(ec2, aws_sdk_ec2, "ec2");
+    #[cfg(feature = "alb")]
+    client!(
+        elbv2,
+        aws_sdk_elasticloadbalancingv2,
+        "elastic_load_balancing_v2"
+    );
+    #[cfg(feature = "elasticache")]
+    client!(elasticache, aws_sdk_elasticache, "elasticache");
+    #[cfg(feature = "lambda")]
+    client!(lambda, aws_sdk_lambda, "lambda");
+    #[cfg(feature = "rds")]
+    client!(rds, aws_sdk_rds, "rds");
+    client!(sts, aws_sdk_sts, "sts");
+}
+
+#[cfg(test)]
+mod test {
+    use super::Endpoints;
+
+    #[test]
+    fn test_resolve() {
+        let mut endpoints = Endpoints::default();
+        assert!(endpoints.resolve("ec2").is_none());
+
+        endpoints.set_global("http://localhost:4566").unwrap();
+        endpoints
+            .set_service("rds", "http://localhost:5000")
+            .unwrap();
+        assert!(endpoints
+            .set_service("s3", "http://localhost:5000")
+            .is_err());
+        assert!(endpoints.set_global("not a url").is_err());
+
+        assert_eq!(
+            endpoints.services.get("rds").unwrap().to_string(),
+            "http://localhost:5000/"
+        );
+        assert!(endpoints.resolve("ec2").is_some());
+        assert!(endpoints.resolve("rds").is_some());
+    }
+}
@@ -0,0 +1,129 @@
+//! Settings from `.aws-sg-cleanup.toml`. Command line arguments take precedence.
+//! Regions scanned without every provider stay incomplete, so `providers` and
+//! `skip_providers` make clean and apply refuse them without
+//! `--allow-incomplete-coverage`.
+//!
+//! ```toml
+//! regions = ["eu-*"]
+//! exclude_regions = ["eu-south-*"]
+//!
+//! [output]
+//! format = "json"
+//! report_format = "markdown"
+//!
+//! [protection]
+//! group_ids = ["sg-0123456789abcdef0"]
+//! names = ["^baseline-"]
+//! descriptions = ["(?i)do not delete"]
+//! tag_keys = ["Protected"]
+//! tags = { Environment = "prod" }
+//! ```
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use anyhow::Context;
+use serde::Deserialize;
+
+use crate::protection::Protection;
+
+pub const DEFAULT_PATH: &str = ".aws-sg-cleanup.toml";
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub regions: Vec<String>,
+    pub exclude_regions: Vec<String>,
+    pub skip_opt_in_regions: bool,
+    pub providers: Vec<String>,
+    pub skip_providers: Vec<String>,
+    pub output: OutputConfig,
+    pub protection: ProtectionConfig,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputConfig {
+    /// Default format of `print`.
+    pub format: Option<String>,
+    /// Default format of `report`.
+    pub report_format: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProtectionConfig {
+    pub group_ids: Vec<String>,
+    /// Regular expressions matched against group names.
+    pub names: Vec<String>,
+    /// Regular expressions matched against group descriptions.
+    pub descriptions: Vec<String>,
+    /// Tags protecting a group whatever their value.
+    pub tag_keys: Vec<String>,
+    /// Tags protecting a group with exactly this value.
+    pub tags: BTreeMap<String, String>,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        toml::from_str(&text).with_context(|| format!("invalid config {}", path.display()))
+    }
+
+    /// Loads `path`, or [`DEFAULT_PATH`] when it exists.
+    pub fn discover(path: Option<&Path>) -> anyhow::Result<Self> {
+        match path {
+            Some(path) => Self::load(path),
+            None if Path::new(DEFAULT_PATH).exists() => Self::load(Path::new(DEFAULT_PATH)),
+            None => Ok(Self::default()),
+        }
+    }
+
+    pub fn protection(&self) -> anyhow::Result<Protection> {
+        let config = &self.protection;
+        Protection::new(
+            config.group_ids.iter().cloned(),
+            &config.names,
+            &config.descriptions,
+            config.tag_keys.iter().cloned(),
+            config.tags.clone(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Config;
+    use crate::security::ExistingGroup;
+
+    #[test]
+    fn test_parse() {
+        let config: Config = toml::from_str(
+            r#"
+            regions = ["eu-*"]
+            exclude_regions = ["eu-south-*"]
+
+            [output]
+            format = "json"
+
+            [protection]
+            names = ["^baseline-"]
+            tags = { Environment = "prod" }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.regions, ["eu-*"]);
+        assert_eq!(config.exclude_regions, ["eu-south-*"]);
+        assert_eq!(config.output.format.as_deref(), Some("json"));
+
+        let protection = config.protection().unwrap();
+        let group = ExistingGroup {
+            group_name: "baseline-ssh".to_string(),
+            ..ExistingGroup::default()
+        };
+        assert!(protection.reason(&group).is_some());
+
+        assert!(toml::from_str::<Config>("region = [\"eu-*\"]").is_err());
+    }
+}
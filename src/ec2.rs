@@ -1,68 +1,55 @@
 use async_trait::async_trait;
-use aws_sdk_ec2::Client;
-use aws_types::SdkConfig;
-use futures::StreamExt;
-use itertools::Itertools;
 
-use crate::security::{SecurityGroups, SecurityGroupsProvider};
+use crate::backend::Backend;
+use crate::error::ProviderError;
+use crate::provider::SecurityGroupsProvider;
+use crate::security::SecurityGroups;
 
 pub struct EC2Groups {}
 
 #[async_trait]
-impl SecurityGroupsProvider<SdkConfig> for EC2Groups {
-    async fn load(config: &SdkConfig) -> SecurityGroups {
-        let client = Client::new(config);
-        let instances_groups = client
-            .describe_instances()
-            .into_paginator()
-            .items()
-            .send()
-            .flat_map(|res| {
-                let reservation = res.unwrap();
-                let classic = reservation
-                    .groups()
-                    .unwrap_or_default()
-                    .iter()
-                    .map(|group| group.group_id().unwrap().to_owned())
-                    .collect_vec();
-                let vpc = reservation
-                    .instances()
-                    .unwrap_or_default()
-                    .iter()
-                    .flat_map(|instance| {
-                        instance
-                            .security_groups()
-                            .unwrap_or_default()
-                            .iter()
-                            .map(|group| group.group_id().unwrap().to_owned())
-                    })
-                    .collect_vec();
-                futures::stream::iter(itertools::chain(classic, vpc))
-            })
-            .collect::<Vec<_>>();
+impl SecurityGroupsProvider for EC2Groups {
+    fn name(&self) -> &'static str {
+        "ec2"
+    }
+
+    fn description(&self) -> &'static str {
+        "Instances and network interfaces"
+    }
+
+    fn iam_actions(&self) -> &'static [&'static str] {
+        &["ec2:DescribeInstances", "ec2:DescribeNetworkInterfaces"]
+    }
 
-        let eni_groups = client
-            .describe_network_interfaces()
-            .into_paginator()
-            .items()
-            .send()
-            .flat_map(|res| {
-                let eni = res.unwrap();
-                futures::stream::iter(
-                    eni.groups()
-                        .unwrap_or_default()
-                        .iter()
-                        .map(|group| group.group_id().unwrap().to_owned())
-                        .collect_vec(),
-                )
-            })
-            .collect::<Vec<_>>();
+    async fn load(&self, backend: &dyn Backend) -> Result<SecurityGroups, ProviderError> {
+        let region = backend.region();
+        let (instances, network_interfaces) = tokio::join!(
+            backend.describe_instances(),
+            backend.describe_network_interfaces()
+        );
+        let instances = instances.map_err(|err| ProviderError::new(self.name(), &region, err))?;
+        let network_interfaces =
+            network_interfaces.map_err(|err| ProviderError::new(self.name(), &region, err))?;
 
-        let (instances_groups, eni_groups) = tokio::join!(instances_groups, eni_groups);
+        let instances = instances.into_iter().map(|instance| {
+            (
+                format!("instance {}", instance.instance_id),
+                instance.security_groups,
+            )
+        });
+        let network_interfaces = network_interfaces.into_iter().map(|eni| {
+            let resource = format!("network interface {}", eni.network_interface_id);
+            // The description names the owner, such as the ECS task attachment.
+            let resource = match eni.description.or(eni.interface_type) {
+                Some(owner) => format!("{} ({})", resource, owner),
+                None => resource,
+            };
+            (resource, eni.security_groups)
+        });
 
-        SecurityGroups::create_from_group_ids(
-            format!("ec2@{}", config.region().unwrap()),
-            itertools::chain(instances_groups, eni_groups),
-        )
+        Ok(SecurityGroups::create_from_resources(
+            format!("{}@{}", self.name(), region),
+            itertools::chain(instances, network_interfaces),
+        ))
     }
 }
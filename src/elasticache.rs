@@ -1,43 +1,65 @@
 use async_trait::async_trait;
-use aws_sdk_elasticache::Client;
-use aws_types::SdkConfig;
-use futures::StreamExt;
 use itertools::Itertools;
 
-use crate::security::{SecurityGroups, SecurityGroupsProvider};
+use crate::backend::{Backend, CacheCluster};
+use crate::error::ProviderError;
+use crate::provider::SecurityGroupsProvider;
+use crate::security::{NameReference, SecurityGroups};
 
 pub struct ElasticacheGroups {}
 
 #[async_trait]
-impl SecurityGroupsProvider<SdkConfig> for ElasticacheGroups {
-    async fn load(config: &SdkConfig) -> SecurityGroups {
-        let client = Client::new(config);
-        let group_ids = client
+impl SecurityGroupsProvider for ElasticacheGroups {
+    fn name(&self) -> &'static str {
+        "elasticache"
+    }
+
+    fn description(&self) -> &'static str {
+        "ElastiCache clusters, including cache security groups"
+    }
+
+    fn iam_actions(&self) -> &'static [&'static str] {
+        &["elasticache:DescribeCacheClusters"]
+    }
+
+    async fn load(&self, backend: &dyn Backend) -> Result<SecurityGroups, ProviderError> {
+        let region = backend.region();
+        let clusters = backend
             .describe_cache_clusters()
-            .into_paginator()
-            .items()
-            .send()
-            .flat_map(|item| {
-                let cluster = item.unwrap();
-                let val = itertools::chain(
-                    cluster
-                        .security_groups()
-                        .into_iter()
-                        .flat_map(|x| x.iter().map(|x| x.security_group_id().unwrap().to_owned())),
-                    cluster.cache_security_groups().into_iter().flat_map(|x| {
-                        x.iter()
-                            .map(|x| x.cache_security_group_name().unwrap().to_owned())
-                    }),
-                )
-                .collect_vec();
-                futures::stream::iter(val)
+            .await
+            .map_err(|err| ProviderError::new(self.name(), &region, err))?;
+
+        let resource =
+            |cluster: &CacheCluster| format!("cache cluster {}", cluster.cache_cluster_id);
+        let resources = clusters
+            .iter()
+            .map(|cluster| (resource(cluster), cluster.security_groups.clone()));
+
+        let source = format!("{}@{}", self.name(), region);
+        let name_references = clusters
+            .iter()
+            .flat_map(|cluster| {
+                cluster
+                    .cache_security_groups
+                    .iter()
+                    .map(move |group_name| (group_name, resource(cluster)))
+            })
+            .into_group_map()
+            .into_iter()
+            .sorted()
+            .map(|(group_name, resources)| NameReference {
+                account_id: String::new(),
+                region: region.clone(),
+                vpc_id: None,
+                group_name: group_name.clone(),
+                source: source.clone(),
+                resources,
             })
-            .collect::<Vec<_>>()
-            .await;
+            .collect_vec();
 
-        SecurityGroups::create_from_group_ids(
-            format!("elasticache@{}", config.region().unwrap()),
-            group_ids.into_iter(),
-        )
+        Ok(SecurityGroups {
+            name_references,
+            ..SecurityGroups::create_from_resources(source, resources)
+        })
     }
 }
@@ -0,0 +1,101 @@
+use aws_smithy_http::result::SdkError;
+use aws_smithy_types::retry::ProvideErrorKind;
+
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+const ACCESS_DENIED_CODES: &[&str] = &[
+    "AccessDenied",
+    "AccessDeniedException",
+    "AuthFailure",
+    "UnauthorizedOperation",
+    "OptInRequired",
+];
+
+const THROTTLING_CODES: &[&str] = &[
+    "Throttling",
+    "ThrottlingException",
+    "ThrottledException",
+    "RequestThrottled",
+    "RequestThrottledException",
+    "RequestLimitExceeded",
+    "TooManyRequestsException",
+    "SlowDown",
+];
+
+/// Failure of a single backend call, classified so callers can decide whether to
+/// retry, report missing permissions or move on.
+#[derive(Debug, thiserror::Error)]
+pub enum BackendError {
+    #[error("{0}")]
+    AccessDenied(BoxError),
+    #[error("{0}")]
+    Throttled(BoxError),
+    /// The service rejected the request, for example with a dependency violation.
+    #[error("{0}")]
+    Service(BoxError),
+    /// The request never got a service response.
+    #[error("{0}")]
+    Transport(BoxError),
+}
+
+impl<E> From<SdkError<E>> for BackendError
+where
+    E: ProvideErrorKind + std::error::Error + Send + Sync + 'static,
+{
+    fn from(err: SdkError<E>) -> Self {
+        let code = match &err {
+            SdkError::ServiceError { err, .. } => err.code().map(ToOwned::to_owned),
+            _ => return Self::Transport(Box::new(err)),
+        };
+        match code.as_deref() {
+            Some(code) if ACCESS_DENIED_CODES.contains(&code) => Self::AccessDenied(Box::new(err)),
+            Some(code) if THROTTLING_CODES.contains(&code) => Self::Throttled(Box::new(err)),
+            _ => Self::Service(Box::new(err)),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ProviderError {
+    #[error("{provider}@{region}: access denied: {source}")]
+    AccessDenied {
+        provider: &'static str,
+        region: String,
+        source: BoxError,
+    },
+    #[error("{provider}@{region}: request throttled: {source}")]
+    Throttled {
+        provider: &'static str,
+        region: String,
+        source: BoxError,
+    },
+    #[error("{provider}@{region}: request failed: {source}")]
+    Request {
+        provider: &'static str,
+        region: String,
+        source: BoxError,
+    },
+}
+
+impl ProviderError {
+    pub fn new(provider: &'static str, region: &str, err: BackendError) -> Self {
+        let region = region.to_owned();
+        match err {
+            BackendError::AccessDenied(source) => Self::AccessDenied {
+                provider,
+                region,
+                source,
+            },
+            BackendError::Throttled(source) => Self::Throttled {
+                provider,
+                region,
+                source,
+            },
+            BackendError::Service(source) | BackendError::Transport(source) => Self::Request {
+                provider,
+                region,
+                source,
+            },
+        }
+    }
+}
@@ -0,0 +1,324 @@
+//! Explains why a group is used, as the chains of rules leading from it to the
+//! resources of services.
+
+use std::collections::{HashMap, VecDeque};
+
+use itertools::Itertools;
+
+use crate::security::{
+    Attachment, ExistingGroup, Rule, RulePeer, SecurityGroups, ServiceReference,
+};
+
+/// One step away from the explained group: `group` allows the previous group of the
+/// path through its ingress or egress `rule`.
+#[derive(Clone, Debug)]
+pub struct Hop<'a> {
+    pub group: &'a ExistingGroup,
+    pub rule: &'a Rule,
+    pub egress: bool,
+}
+
+/// How a service reaches a group: the rules from the group to the group the
+/// service holds, and the resource holding it.
+#[derive(Clone, Debug)]
+pub struct ReferencePath<'a> {
+    pub hops: Vec<Hop<'a>>,
+    pub service: &'a ServiceReference,
+    /// Missing for inventories saved before resources were recorded.
+    pub attachment: Option<&'a Attachment>,
+}
+
+impl ReferencePath<'_> {
+    /// The group the service holds.
+    pub fn held_group<'b>(&'b self, group_id: &'b str) -> &'b str {
+        self.hops
+            .last()
+            .map_or(group_id, |hop| hop.group.group_id.as_str())
+    }
+
+    /// One sentence per hop and a last one for the service, such as
+    /// `sg-a is referenced by ingress rule tcp 443 from sg-a of sg-b (web)`.
+    pub fn describe(&self, group_id: &str) -> Vec<String> {
+        let mut previous = group_id;
+        let mut lines = vec![];
+        for hop in self.hops.iter() {
+            let (direction, preposition) = match hop.egress {
+                true => ("egress", "to"),
+                false => ("ingress", "from"),
+            };
+            let mut line = format!(
+                "{} is referenced by {} rule {} {} {} {}",
+                previous,
+                direction,
+                hop.rule.protocol_name(),
+                hop.rule.ports(),
+                preposition,
+                hop.rule.peer
+            );
+            if let Some(description) = &hop.rule.description {
+                line.push_str(&format!(" \"{}\"", description));
+            }
+            line.push_str(&format!(
+                " of {} ({})",
+                hop.group.group_id, hop.group.group_name
+            ));
+            lines.push(line);
+            previous = &hop.group.group_id;
+        }
+        lines.push(match self.attachment {
+            Some(attachment) => format!(
+                "{} is attached to {} through {}",
+                previous, attachment.resource, self.service
+            ),
+            None => format!("{} is used by {}", previous, self.service),
+        });
+        lines
+    }
+}
+
+/// The paths found by [`reference_paths`].
+#[derive(Clone, Debug)]
+pub struct ReferencePaths<'a> {
+    pub paths: Vec<ReferencePath<'a>>,
+    /// More paths than the limit were found, only the shortest are kept.
+    pub truncated: bool,
+}
+
+/// Paths from `group_id` to service references, shortest first, at most `limit`.
+/// Paths never visit a group twice, and each group is expanded at most `limit` times,
+/// which keeps dense or cyclic graphs cheap while still finding the shortest path to
+/// every reachable service.
+pub fn reference_paths<'a>(
+    groups: &'a SecurityGroups,
+    group_id: &str,
+    limit: usize,
+) -> ReferencePaths<'a> {
+    let graph = groups.reference_graph();
+    let mut referencing: HashMap<&str, Vec<Hop<'a>>> = HashMap::new();
+    for group in groups.existing_groups.iter().sorted_by_key(|x| &x.group_id) {
+        let rules = itertools::chain(
+            group.ingress.iter().map(|rule| (rule, false)),
+            group.egress.iter().map(|rule| (rule, true)),
+        );
+        for (rule, egress) in rules {
+            match &rule.peer {
+                RulePeer::Group { group_id, .. } if *group_id != group.group_id => {
+                    let hops = referencing.entry(group_id).or_default();
+                    // One rule is enough evidence for each group.
+                    if !hops.iter().any(|hop| hop.group.group_id == group.group_id) {
+                        hops.push(Hop {
+                            group,
+                            rule,
+                            egress,
+                        });
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let mut paths = vec![];
+    let mut expanded: HashMap<&str, usize> = HashMap::new();
+    let mut queue: VecDeque<(&str, Vec<Hop<'a>>)> = VecDeque::from([(group_id, vec![])]);
+    while let Some((current, hops)) = queue.pop_front() {
+        let count = expanded.entry(current).or_default();
+        if *count >= limit {
+            continue;
+        }
+        *count += 1;
+        let services = groups
+            .external_references
+            .get(current)
+            .into_iter()
+            .flatten()
+            .sorted();
+        let attachments = groups.attachments.get(current);
+        for service in services {
+            let mut attached = attachments
+                .into_iter()
+                .flatten()
+                .filter(|x| x.reference == *service)
+                .sorted()
+                .peekable();
+            if attached.peek().is_none() {
+                paths.push(ReferencePath {
+                    hops: hops.clone(),
+                    service,
+                    attachment: None,
+                });
+            }
+            for attachment in attached {
+                paths.push(ReferencePath {
+                    hops: hops.clone(),
+                    service,
+                    attachment: Some(attachment),
+                });
+            }
+        }
+        if paths.len() > limit {
+            paths.truncate(limit);
+            return ReferencePaths {
+                paths,
+                truncated: true,
+            };
+        }
+
+        for hop in referencing.get(current).into_iter().flatten() {
+            let next = hop.group.group_id.as_str();
+            let visited = next == group_id || hops.iter().any(|x| x.group.group_id == next);
+            let exhausted = expanded.get(next).is_some_and(|count| *count >= limit);
+            // Groups no service uses lead nowhere.
+            if visited || exhausted || !graph.is_used(next) {
+                continue;
+            }
+            let mut hops = hops.clone();
+            hops.push(hop.clone());
+            queue.push_back((next, hops));
+        }
+    }
+    ReferencePaths {
+        paths,
+        truncated: false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use maplit::hashmap;
+
+    use super::reference_paths;
+    use crate::security::{Attachment, ExistingGroup, Rule, RulePeer, SecurityGroups};
+
+    fn group(group_id: &str, references: &[&str]) -> ExistingGroup {
+        ExistingGroup {
+            group_id: group_id.to_string(),
+            group_name: group_id.to_string(),
+            ingress: references
+                .iter()
+                .map(|peer| Rule {
+                    protocol: "tcp".to_string(),
+                    from_port: Some(443),
+                    to_port: Some(443),
+                    peer: RulePeer::Group {
+                        group_id: peer.to_string(),
+                        user_id: None,
+                    },
+                    description: None,
+                })
+                .collect(),
+            references: references.iter().map(|x| x.to_string()).collect(),
+            ..ExistingGroup::default()
+        }
+    }
+
+    #[test]
+    fn test_reference_paths() {
+        let groups = SecurityGroups {
+            existing_groups: vec![
+                group("sg-a", &["sg-c"]),
+                group("sg-b", &["sg-a", "sg-b"]),
+                group("sg-c", &["sg-b"]),
+                group("sg-d", &["sg-a"]),
+            ],
+            ..SecurityGroups::create_from_resources(
+                "ec2@eu-west-1".to_string(),
+                [
+                    (
+                        "network interface eni-1".to_string(),
+                        vec!["sg-b".to_string()],
+                    ),
+                    ("instance i-1".to_string(), vec!["sg-a".to_string()]),
+                ]
+                .into_iter(),
+            )
+        };
+
+        let paths = reference_paths(&groups, "sg-a", 10).paths;
+        let lines = paths.iter().map(|x| x.describe("sg-a")).collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            vec![
+                vec!["sg-a is attached to instance i-1 through ec2@eu-west-1"],
+                vec![
+                    "sg-a is referenced by ingress rule tcp 443 from sg-a of sg-b (sg-b)",
+                    "sg-b is attached to network interface eni-1 through ec2@eu-west-1",
+                ],
+            ]
+        );
+        assert_eq!(paths[1].held_group("sg-a"), "sg-b");
+
+        // sg-b references sg-c back through sg-c's own rule, which must not loop.
+        let paths = reference_paths(&groups, "sg-c", 10).paths;
+        assert_eq!(paths.len(), 2);
+        assert_eq!(paths[0].held_group("sg-c"), "sg-a");
+        assert_eq!(paths[1].hops.len(), 2);
+        assert_eq!(paths[1].held_group("sg-c"), "sg-b");
+
+        assert!(reference_paths(&groups, "sg-d", 10).paths.is_empty());
+
+        // Exactly as many paths as the limit are not truncated, one more is.
+        let exact = reference_paths(&groups, "sg-c", 2);
+        assert_eq!(exact.paths.len(), 2);
+        assert!(!exact.truncated);
+        let cut = reference_paths(&groups, "sg-c", 1);
+        assert_eq!(cut.paths.len(), 1);
+        assert!(cut.truncated);
+    }
+
+    #[test]
+    fn test_reference_paths_in_dense_graph() {
+        // Every group of a clique references every other one, and the only service sits
+        // at the end of a long chain leaving it.
+        let clique = (0..12).map(|i| format!("sg-c{}", i)).collect::<Vec<_>>();
+        let mut existing_groups = clique
+            .iter()
+            .map(|id| {
+                let others = clique
+                    .iter()
+                    .filter(|x| *x != id)
+                    .map(String::as_str)
+                    .collect::<Vec<_>>();
+                group(id, &others)
+            })
+            .collect::<Vec<_>>();
+        let mut previous = "sg-c11".to_string();
+        for i in 0..15 {
+            let id = format!("sg-h{}", i);
+            existing_groups.push(group(&id, &[&previous]));
+            previous = id;
+        }
+        let groups = SecurityGroups {
+            existing_groups,
+            external_references: hashmap![previous.clone() => vec!["ec2@eu-west-1".into()]],
+            ..SecurityGroups::default()
+        };
+
+        let paths = reference_paths(&groups, "sg-c0", 3).paths;
+        assert!(!paths.is_empty());
+        assert_eq!(paths[0].hops.len(), 16);
+        assert_eq!(paths[0].held_group("sg-c0"), previous);
+    }
+
+    #[test]
+    fn test_reference_paths_without_attachments() {
+        let groups = SecurityGroups {
+            existing_groups: vec![group("sg-a", &[])],
+            external_references: hashmap![
+                "sg-a".to_string() => vec!["lambda@eu-west-1".into()],
+            ],
+            attachments: hashmap![
+                "sg-a".to_string() => vec![Attachment {
+                    reference: "ec2@eu-west-1".into(),
+                    resource: "instance i-1".to_string(),
+                }],
+            ],
+            ..SecurityGroups::default()
+        };
+        let paths = reference_paths(&groups, "sg-a", 10).paths;
+        assert_eq!(
+            paths[0].describe("sg-a"),
+            vec!["sg-a is used by lambda@eu-west-1"]
+        );
+    }
+}
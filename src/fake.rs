@@ -0,0 +1,233 @@
+use std::{collections::BTreeMap, fs::File, io::BufReader, path::Path, sync::Arc};
+
+use anyhow::Context;
+use async_trait::async_trait;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::backend::{
+    Backend, CacheCluster, DbCluster, DbInstance, Function, Instance, LoadBalancer,
+    NetworkInterface, SecurityGroup,
+};
+use crate::error::BackendError;
+use crate::security::{Rule, RulePeer};
+
+/// State of every region of one account, usually loaded from a JSON fixture file.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Fixture {
+    pub regions: BTreeMap<String, RegionFixture>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RegionFixture {
+    pub security_groups: Vec<SecurityGroup>,
+    pub instances: Vec<Instance>,
+    pub network_interfaces: Vec<NetworkInterface>,
+    pub load_balancers: Vec<LoadBalancer>,
+    pub cache_clusters: Vec<CacheCluster>,
+    pub functions: Vec<Function>,
+    pub db_instances: Vec<DbInstance>,
+    pub db_clusters: Vec<DbCluster>,
+    /// Backend calls failing with access denied, by method name, or as
+    /// `describe_security_groups:<group_id>` for the lookup of a single group.
+    pub denied: Vec<String>,
+}
+
+impl Fixture {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let file =
+            File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+        serde_json::from_reader(BufReader::new(file))
+            .with_context(|| format!("failed to parse {}", path.display()))
+    }
+
+    /// One backend per region, sharing nothing with the fixture.
+    pub fn backends(&self) -> BTreeMap<String, FakeBackend> {
+        self.regions
+            .iter()
+            .map(|(region, fixture)| {
+                let backend = FakeBackend {
+                    region: region.clone(),
+                    state: Arc::new(Mutex::new(fixture.clone())),
+                };
+                (region.clone(), backend)
+            })
+            .collect()
+    }
+}
+
+/// In-memory [`Backend`] for one region. Clones share state, so changes made by the
+/// cleanup are visible through [`FakeBackend::state`].
+#[derive(Clone)]
+pub struct FakeBackend {
+    region: String,
+    state: Arc<Mutex<RegionFixture>>,
+}
+
+impl FakeBackend {
+    pub fn state(&self) -> RegionFixture {
+        self.state.lock().clone()
+    }
+
+    fn check(&self, call: &str) -> Result<(), BackendError> {
+        if self.state.lock().denied.iter().any(|x| x == call) {
+            return Err(BackendError::AccessDenied(
+                format!("{} denied in {}", call, self.region).into(),
+            ));
+        }
+        Ok(())
+    }
+
+    fn describe<T: Clone>(
+        &self,
+        call: &str,
+        items: impl FnOnce(&RegionFixture) -> &Vec<T>,
+    ) -> Result<Vec<T>, BackendError> {
+        self.check(call)?;
+        Ok(items(&self.state.lock()).clone())
+    }
+
+    fn revoke(
+        &self,
+        group_id: &str,
+        rules: &[Rule],
+        direction: impl FnOnce(&mut SecurityGroup) -> &mut Vec<Rule>,
+    ) -> Result<(), BackendError> {
+        let mut state = self.state.lock();
+        let group = state
+            .security_groups
+            .iter_mut()
+            .find(|group| group.group_id == group_id)
+            .ok_or_else(|| not_found(group_id))?;
+        direction(group).retain(|rule| !rules.contains(rule));
+        Ok(())
+    }
+}
+
+fn not_found(group_id: &str) -> BackendError {
+    BackendError::Service(format!("InvalidGroup.NotFound: {}", group_id).into())
+}
+
+/// Anything still attached to or referencing `group_id`, the way EC2 refuses to
+/// delete a group with a dependency violation.
+fn dependents(state: &RegionFixture, group_id: &str) -> Vec<String> {
+    let uses = |groups: &Vec<String>| groups.iter().any(|x| x == group_id);
+    let rule_references = |rules: &Vec<Rule>| {
+        rules.iter().any(
+            |rule| matches!(&rule.peer, RulePeer::Group { group_id: peer, .. } if peer == group_id),
+        )
+    };
+    let groups = state
+        .security_groups
+        .iter()
+        .filter(|group| group.group_id != group_id)
+        .filter(|group| rule_references(&group.ingress) || rule_references(&group.egress))
+        .map(|group| group.group_id.clone());
+    let instances = state
+        .instances
+        .iter()
+        .filter(|x| uses(&x.security_groups))
+        .map(|x| x.instance_id.clone());
+    let enis = state
+        .network_interfaces
+        .iter()
+        .filter(|x| uses(&x.security_groups))
+        .map(|x| x.network_interface_id.clone());
+    groups.chain(instances).chain(enis).collect()
+}
+
+#[async_trait]
+impl Backend for FakeBackend {
+    fn region(&self) -> String {
+        self.region.clone()
+    }
+
+    async fn describe_security_groups(&self) -> Result<Vec<SecurityGroup>, BackendError> {
+        self.describe("describe_security_groups", |x| &x.security_groups)
+    }
+
+    async fn describe_security_group(
+        &self,
+        group_id: &str,
+    ) -> Result<Option<SecurityGroup>, BackendError> {
+        self.check("describe_security_groups")?;
+        self.check(&format!("describe_security_groups:{}", group_id))?;
+        Ok(self
+            .state
+            .lock()
+            .security_groups
+            .iter()
+            .find(|group| group.group_id == group_id)
+            .cloned())
+    }
+
+    async fn describe_instances(&self) -> Result<Vec<Instance>, BackendError> {
+        self.describe("describe_instances", |x| &x.instances)
+    }
+
+    async fn describe_network_interfaces(&self) -> Result<Vec<NetworkInterface>, BackendError> {
+        self.describe("describe_network_interfaces", |x| &x.network_interfaces)
+    }
+
+    async fn describe_load_balancers(&self) -> Result<Vec<LoadBalancer>, BackendError> {
+        self.describe("describe_load_balancers", |x| &x.load_balancers)
+    }
+
+    async fn describe_cache_clusters(&self) -> Result<Vec<CacheCluster>, BackendError> {
+        self.describe("describe_cache_clusters", |x| &x.cache_clusters)
+    }
+
+    async fn list_functions(&self) -> Result<Vec<Function>, BackendError> {
+        self.describe("list_functions", |x| &x.functions)
+    }
+
+    async fn describe_db_instances(&self) -> Result<Vec<DbInstance>, BackendError> {
+        self.describe("describe_db_instances", |x| &x.db_instances)
+    }
+
+    async fn describe_db_clusters(&self) -> Result<Vec<DbCluster>, BackendError> {
+        self.describe("describe_db_clusters", |x| &x.db_clusters)
+    }
+
+    async fn revoke_ingress(&self, group_id: &str, rules: &[Rule]) -> Result<(), BackendError> {
+        self.check("revoke_security_group_ingress")?;
+        self.revoke(group_id, rules, |group| &mut group.ingress)
+    }
+
+    async fn revoke_egress(&self, group_id: &str, rules: &[Rule]) -> Result<(), BackendError> {
+        self.check("revoke_security_group_egress")?;
+        self.revoke(group_id, rules, |group| &mut group.egress)
+    }
+
+    async fn delete_security_group(&self, group_id: &str) -> Result<(), BackendError> {
+        self.check("delete_security_group")?;
+        let mut state = self.state.lock();
+        let group = state
+            .security_groups
+            .iter()
+            .find(|group| group.group_id == group_id)
+            .ok_or_else(|| not_found(group_id))?;
+        if group.group_name == "default" {
+            return Err(BackendError::Service(
+                format!("CannotDelete: {} is a default group", group_id).into(),
+            ));
+        }
+        let dependents = dependents(&state, group_id);
+        if !dependents.is_empty() {
+            return Err(BackendError::Service(
+                format!(
+                    "DependencyViolation: {} is used by {}",
+                    group_id,
+                    dependents.join(", ")
+                )
+                .into(),
+            ));
+        }
+        state
+            .security_groups
+            .retain(|group| group.group_id != group_id);
+        Ok(())
+    }
+}
@@ -0,0 +1,382 @@
+use std::collections::{HashMap, HashSet};
+
+use itertools::Itertools;
+
+use crate::security::{ExistingGroup, SecurityGroups, ServiceReference};
+
+/// Reverse reference index over all known groups.
+///
+/// A group is used by the services referencing it directly and, transitively, by the
+/// services using any existing group whose rules reference it. Groups referencing each
+/// other form strongly connected components that share one set of services, so the sets
+/// are computed once per component over the condensed graph.
+pub struct ReferenceGraph<'a> {
+    index: HashMap<&'a str, usize>,
+    services: Vec<&'a ServiceReference>,
+    component: Vec<usize>,
+    component_services: Vec<HashSet<usize>>,
+}
+
+impl<'a> ReferenceGraph<'a> {
+    pub fn new(groups: &'a SecurityGroups) -> Self {
+        let mut index: HashMap<&'a str, usize> = HashMap::new();
+        let mut intern = |group_id: &'a str| {
+            let next = index.len();
+            *index.entry(group_id).or_insert(next)
+        };
+
+        let mut referenced_by: Vec<(usize, usize)> = vec![];
+        for group in groups.existing_groups.iter() {
+            let source = intern(&group.group_id);
+            for target in group.references.iter() {
+                referenced_by.push((intern(target), source));
+            }
+        }
+        let mut service_index: HashMap<&'a ServiceReference, usize> = HashMap::new();
+        let mut services = vec![];
+        let mut direct: Vec<(usize, usize)> = vec![];
+        for (group_id, names) in groups.external_references.iter() {
+            let node = intern(group_id);
+            for name in names.iter() {
+                let service = *service_index.entry(name).or_insert_with(|| {
+                    services.push(name);
+                    services.len() - 1
+                });
+                direct.push((node, service));
+            }
+        }
+
+        let mut edges = vec![vec![]; index.len()];
+        for (target, source) in referenced_by {
+            edges[target].push(source);
+        }
+
+        let (component, components) = strongly_connected_components(&edges);
+
+        let mut component_services = vec![HashSet::new(); components];
+        for (node, service) in direct {
+            component_services[component[node]].insert(service);
+        }
+        let mut members = vec![vec![]; components];
+        for (node, c) in component.iter().enumerate() {
+            members[*c].push(node);
+        }
+        // Tarjan emits components in reverse topological order, so every component
+        // reachable from `c` has a lower index and is already complete.
+        for c in 0..components {
+            let reachable: HashSet<usize> = members[c]
+                .iter()
+                .flat_map(|node| edges[*node].iter().map(|target| component[*target]))
+                .filter(|other| *other != c)
+                .collect();
+            for other in reachable {
+                let inherited = component_services[other].clone();
+                component_services[c].extend(inherited);
+            }
+        }
+
+        Self {
+            index,
+            services,
+            component,
+            component_services,
+        }
+    }
+
+    pub fn referencing_services(&self, group_id: &str) -> HashSet<&'a ServiceReference> {
+        self.index
+            .get(group_id)
+            .map(|node| {
+                self.component_services[self.component[*node]]
+                    .iter()
+                    .map(|service| self.services[*service])
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn is_used(&self, group_id: &str) -> bool {
+        self.index
+            .get(group_id)
+            .is_some_and(|node| !self.component_services[self.component[*node]].is_empty())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum DeletionStep<'a> {
+    /// Revoke the rules of `group` that reference `referenced` groups.
+    Revoke {
+        group: &'a ExistingGroup,
+        referenced: Vec<&'a str>,
+    },
+    Delete(&'a ExistingGroup),
+}
+
+/// Orders the deletion of `groups` so that no group is deleted while another group
+/// in the set still references it. Groups that reference each other in a cycle first
+/// get the rules that close the cycle revoked, then are deleted together.
+pub fn deletion_order<'a>(groups: &[&'a ExistingGroup]) -> Vec<DeletionStep<'a>> {
+    let index: HashMap<&str, usize> = groups
+        .iter()
+        .enumerate()
+        .map(|(node, group)| (group.group_id.as_str(), node))
+        .collect();
+    let edges = groups
+        .iter()
+        .map(|group| {
+            group
+                .references
+                .iter()
+                .filter_map(|target| index.get(target.as_str()).copied())
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    let (component, components) = strongly_connected_components(&edges);
+    let mut members = vec![vec![]; components];
+    for (node, c) in component.iter().enumerate() {
+        members[*c].push(node);
+    }
+
+    // Referenced groups come first in Tarjan order, referencing groups must go first here.
+    let mut steps = vec![];
+    for nodes in members.iter().rev() {
+        if nodes.len() > 1 {
+            for node in nodes.iter() {
+                let referenced = edges[*node]
+                    .iter()
+                    .filter(|target| component[**target] == component[*node])
+                    .map(|target| groups[*target].group_id.as_str())
+                    .sorted()
+                    .collect_vec();
+                steps.push(DeletionStep::Revoke {
+                    group: groups[*node],
+                    referenced,
+                });
+            }
+        }
+        steps.extend(nodes.iter().map(|node| DeletionStep::Delete(groups[*node])));
+    }
+    steps
+}
+
+/// Iterative Tarjan. Returns the component of every node and the number of components,
+/// numbered in reverse topological order.
+fn strongly_connected_components(edges: &[Vec<usize>]) -> (Vec<usize>, usize) {
+    const UNVISITED: usize = usize::MAX;
+
+    let n = edges.len();
+    let mut order = vec![UNVISITED; n];
+    let mut low = vec![0; n];
+    let mut on_stack = vec![false; n];
+    let mut component = vec![UNVISITED; n];
+    let mut stack = vec![];
+    let mut components = 0;
+    let mut next_order = 0;
+
+    for root in 0..n {
+        if order[root] != UNVISITED {
+            continue;
+        }
+        let mut call_stack = vec![(root, 0)];
+        order[root] = next_order;
+        low[root] = next_order;
+        next_order += 1;
+        stack.push(root);
+        on_stack[root] = true;
+
+        while let Some((node, edge)) = call_stack.pop() {
+            if let Some(&next) = edges[node].get(edge) {
+                call_stack.push((node, edge + 1));
+                if order[next] == UNVISITED {
+                    order[next] = next_order;
+                    low[next] = next_order;
+                    next_order += 1;
+                    stack.push(next);
+                    on_stack[next] = true;
+                    call_stack.push((next, 0));
+                } else if on_stack[next] {
+                    low[node] = low[node].min(order[next]);
+                }
+                continue;
+            }
+
+            if low[node] == order[node] {
+                while let Some(member) = stack.pop() {
+                    on_stack[member] = false;
+                    component[member] = components;
+                    if member == node {
+                        break;
+                    }
+                }
+                components += 1;
+            }
+            if let Some(&(parent, _)) = call_stack.last() {
+                low[parent] = low[parent].min(low[node]);
+            }
+        }
+    }
+
+    (component, components)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::{HashMap, HashSet};
+
+    use maplit::hashmap;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::DeletionStep;
+    use crate::security::{ExistingGroup, SecurityGroups, ServiceReference};
+
+    fn group(group_id: &str, references: &[&str]) -> ExistingGroup {
+        ExistingGroup {
+            region: "eu-west-1".to_string(),
+            group_id: group_id.to_string(),
+            group_name: group_id.to_string(),
+            group_description: group_id.to_string(),
+            references: references.iter().map(|x| x.to_string()).collect(),
+            ..ExistingGroup::default()
+        }
+    }
+
+    /// The recursive scan the graph replaces, kept as an oracle.
+    fn naive_referencing_services(
+        groups: &SecurityGroups,
+        group_id: &str,
+        mut visited: HashSet<String>,
+    ) -> HashSet<ServiceReference> {
+        let direct_refs = groups
+            .external_references
+            .get(group_id)
+            .cloned()
+            .unwrap_or_default();
+        visited.insert(group_id.to_string());
+        let indirect_refs = groups
+            .existing_groups
+            .iter()
+            .filter(|group| {
+                group.references.contains(group_id) && !visited.contains(&group.group_id)
+            })
+            .flat_map(|group| naive_referencing_services(groups, &group.group_id, visited.clone()));
+        itertools::chain(direct_refs, indirect_refs).collect()
+    }
+
+    fn assert_matches_naive(groups: &SecurityGroups) {
+        let graph = groups.reference_graph();
+        for group in groups.existing_groups.iter() {
+            let expected = naive_referencing_services(groups, &group.group_id, HashSet::new());
+            let actual: HashSet<ServiceReference> = graph
+                .referencing_services(&group.group_id)
+                .into_iter()
+                .cloned()
+                .collect();
+            assert_eq!(actual, expected, "{}", group.group_id);
+            assert_eq!(graph.is_used(&group.group_id), !expected.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_chain_and_cycle() {
+        let groups = SecurityGroups {
+            external_references: hashmap![
+                "a".to_string() => vec!["ec2@eu-west-1".into()],
+                "x".to_string() => vec!["rds@eu-west-1".into()],
+            ],
+            existing_groups: vec![
+                group("a", &[]),
+                group("b", &[]),
+                group("c", &["b"]),
+                group("d", &["e"]),
+                group("e", &["d"]),
+                group("f", &["a"]),
+                group("x", &["a"]),
+            ],
+            ..SecurityGroups::default()
+        };
+        let graph = groups.reference_graph();
+        assert!(graph.is_used("a"));
+        assert_eq!(
+            graph
+                .referencing_services("a")
+                .into_iter()
+                .map(|x| x.source.as_str())
+                .collect::<HashSet<_>>(),
+            ["ec2@eu-west-1", "rds@eu-west-1"].into_iter().collect()
+        );
+        assert!(!graph.is_used("b"));
+        assert!(!graph.is_used("d"));
+        assert!(!graph.is_used("missing"));
+        assert_matches_naive(&groups);
+        assert_eq!(
+            groups
+                .find_unused()
+                .into_iter()
+                .map(|group| group.group_id.as_str())
+                .collect::<HashSet<_>>(),
+            ["b", "c", "d", "e", "f"].into_iter().collect()
+        );
+    }
+
+    #[test]
+    fn test_random_inventories() {
+        let mut rng = StdRng::seed_from_u64(7);
+        for _ in 0..50 {
+            let n = rng.gen_range(1..12);
+            let ids = (0..n).map(|i| format!("sg-{}", i)).collect::<Vec<_>>();
+            let existing_groups = ids
+                .iter()
+                .map(|id| {
+                    let references = ids
+                        .iter()
+                        .filter(|other| *other != id && rng.gen_bool(0.2))
+                        .map(|x| x.as_str())
+                        .collect::<Vec<_>>();
+                    group(id, &references)
+                })
+                .collect();
+            let mut external_references = HashMap::new();
+            for id in ids.iter() {
+                if rng.gen_bool(0.15) {
+                    let service = format!("svc-{}", rng.gen_range(0..3));
+                    external_references.insert(id.clone(), vec![service.into()]);
+                }
+            }
+            assert_matches_naive(&SecurityGroups {
+                external_references,
+                existing_groups,
+                ..SecurityGroups::default()
+            });
+        }
+    }
+
+    #[test]
+    fn test_deletion_order() {
+        let a = group("a", &["b"]);
+        let b = group("b", &["c"]);
+        let c = group("c", &[]);
+        let d = group("d", &["e", "c"]);
+        let e = group("e", &["d"]);
+        let steps = super::deletion_order(&[&c, &b, &a, &e, &d]);
+
+        let position = |step: &DeletionStep| steps.iter().position(|x| x == step).unwrap();
+        assert!(position(&DeletionStep::Delete(&a)) < position(&DeletionStep::Delete(&b)));
+        assert!(position(&DeletionStep::Delete(&b)) < position(&DeletionStep::Delete(&c)));
+        assert!(position(&DeletionStep::Delete(&d)) < position(&DeletionStep::Delete(&c)));
+
+        let revoke_d = DeletionStep::Revoke {
+            group: &d,
+            referenced: vec!["e"],
+        };
+        let revoke_e = DeletionStep::Revoke {
+            group: &e,
+            referenced: vec!["d"],
+        };
+        for revoke in [&revoke_d, &revoke_e] {
+            assert!(position(revoke) < position(&DeletionStep::Delete(&d)));
+            assert!(position(revoke) < position(&DeletionStep::Delete(&e)));
+        }
+        assert_eq!(steps.len(), 7);
+    }
+}
@@ -0,0 +1,256 @@
+use anyhow::{bail, Context};
+use aws_smithy_xml::decode::{try_data, Document};
+use http::{header, Method, Request, Uri};
+
+use crate::client::ClientConfig;
+use crate::signing;
+
+/// IAM is a global service signed in us-east-1. `aws-sdk-iam` is not a dependency of this
+/// crate for the single call it makes, so the request is signed and sent directly.
+const SIGNING_REGION: &str = "us-east-1";
+const DEFAULT_ENDPOINT: &str = "https://iam.amazonaws.com/";
+
+/// Actions of `actions` that `principal_arn` is not allowed to call, according to
+/// `iam:SimulatePrincipalPolicy`.
+pub async fn denied_actions(
+    config: &ClientConfig,
+    principal_arn: &str,
+    actions: &[&str],
+) -> anyhow::Result<Vec<String>> {
+    let mut denied = vec![];
+    let mut marker: Option<String> = None;
+    loop {
+        let mut form = form_urlencoded::Serializer::new(String::new());
+        form.append_pair("Action", "SimulatePrincipalPolicy")
+            .append_pair("Version", "2010-05-08")
+            .append_pair("PolicySourceArn", principal_arn);
+        for (i, action) in actions.iter().enumerate() {
+            form.append_pair(&format!("ActionNames.member.{}", i + 1), action);
+        }
+        if let Some(marker) = &marker {
+            form.append_pair("Marker", marker);
+        }
+        let response = send(config, form.finish()).await?;
+        let simulation =
+            parse_simulation(&response).context("invalid SimulatePrincipalPolicy response")?;
+        denied.extend(
+            simulation
+                .decisions
+                .into_iter()
+                .filter(|(_, decision)| decision != "allowed")
+                .map(|(action, _)| action),
+        );
+        marker = simulation.marker;
+        if marker.is_none() {
+            return Ok(denied);
+        }
+    }
+}
+
+/// One page of `SimulatePrincipalPolicy` results.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Simulation {
+    /// Action name and decision of every evaluation result.
+    decisions: Vec<(String, String)>,
+    /// Where the next page starts, when the results are truncated.
+    marker: Option<String>,
+}
+
+fn parse_simulation(xml: &str) -> anyhow::Result<Simulation> {
+    let mut doc = Document::new(xml);
+    let mut root = doc.root_element()?;
+    let mut simulation = Simulation::default();
+    let mut truncated = false;
+    while let Some(mut result) = root.next_tag() {
+        if !result.start_el().matches("SimulatePrincipalPolicyResult") {
+            continue;
+        }
+        while let Some(mut tag) = result.next_tag() {
+            match tag.start_el().local() {
+                "EvaluationResults" => {
+                    while let Some(mut member) = tag.next_tag() {
+                        let (mut action, mut decision) = (None, None);
+                        while let Some(mut field) = member.next_tag() {
+                            match field.start_el().local() {
+                                "EvalActionName" => action = Some(try_data(&mut field)?),
+                                "EvalDecision" => decision = Some(try_data(&mut field)?),
+                                _ => {}
+                            }
+                        }
+                        let (Some(action), Some(decision)) = (action, decision) else {
+                            bail!("evaluation result without an action or a decision");
+                        };
+                        simulation
+                            .decisions
+                            .push((action.into_owned(), decision.into_owned()));
+                    }
+                }
+                "IsTruncated" => truncated = try_data(&mut tag)? == "true",
+                "Marker" => simulation.marker = Some(try_data(&mut tag)?.into_owned()),
+                _ => {}
+            }
+        }
+    }
+    if !truncated {
+        simulation.marker = None;
+    }
+    Ok(simulation)
+}
+
+/// Code and message of an IAM error response.
+fn parse_error(xml: &str) -> anyhow::Result<(String, String)> {
+    let mut doc = Document::new(xml);
+    let mut root = doc.root_element()?;
+    let (mut code, mut message) = (String::new(), String::new());
+    while let Some(mut error) = root.next_tag() {
+        if !error.start_el().matches("Error") {
+            continue;
+        }
+        while let Some(mut tag) = error.next_tag() {
+            match tag.start_el().local() {
+                "Code" => code = try_data(&mut tag)?.into_owned(),
+                "Message" => message = try_data(&mut tag)?.into_owned(),
+                _ => {}
+            }
+        }
+    }
+    Ok((code, message))
+}
+
+async fn send(config: &ClientConfig, body: String) -> anyhow::Result<String> {
+    let uri = match config.endpoints().uri("iam") {
+        Some(uri) => uri.clone(),
+        None => Uri::from_static(DEFAULT_ENDPOINT),
+    };
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri(uri)
+        .header(
+            header::CONTENT_TYPE,
+            "application/x-www-form-urlencoded; charset=utf-8",
+        )
+        .body(body)?;
+    let (status, text) = signing::send(config, "iam", SIGNING_REGION, request).await?;
+    if !status.is_success() {
+        let (code, message) = parse_error(&text).unwrap_or_default();
+        let code = if code.is_empty() {
+            "UnknownError"
+        } else {
+            &code
+        };
+        bail!("iam request failed with {}: {} {}", status, code, message);
+    }
+    Ok(text)
+}
+
+/// The IAM ARN policies are attached to for a caller identity ARN. Assumed role
+/// sessions map to their role, assuming the role has the default `/` path.
+pub fn principal_arn(caller_arn: &str) -> anyhow::Result<String> {
+    let Some((prefix, resource)) = caller_arn.split_once(":assumed-role/") else {
+        if caller_arn.ends_with(":root") {
+            bail!("policies of the root user cannot be simulated");
+        }
+        return Ok(caller_arn.to_owned());
+    };
+    let role = resource.split('/').next().unwrap_or_default();
+    Ok(format!(
+        "{}:role/{}",
+        prefix.replacen(":sts:", ":iam:", 1),
+        role
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_error, parse_simulation, principal_arn, Simulation};
+
+    #[test]
+    fn test_principal_arn() {
+        assert_eq!(
+            principal_arn("arn:aws:sts::123456789012:assumed-role/Auditor/session").unwrap(),
+            "arn:aws:iam::123456789012:role/Auditor"
+        );
+        assert_eq!(
+            principal_arn("arn:aws:iam::123456789012:user/alice").unwrap(),
+            "arn:aws:iam::123456789012:user/alice"
+        );
+        assert!(principal_arn("arn:aws:iam::123456789012:root").is_err());
+    }
+
+    #[test]
+    fn test_parse_simulation() {
+        // Shaped like a captured response, decisions before action names and nested
+        // members in the statements and resource results.
+        let xml = r#"<SimulatePrincipalPolicyResponse xmlns="https://iam.amazonaws.com/doc/2010-05-08/">
+  <SimulatePrincipalPolicyResult>
+    <IsTruncated>true</IsTruncated>
+    <Marker>page&#43;2&amp;more</Marker>
+    <EvaluationResults>
+      <member>
+        <EvalResourceName>*</EvalResourceName>
+        <EvalDecision>allowed</EvalDecision>
+        <MatchedStatements>
+          <member>
+            <SourcePolicyId>ReadOnly</SourcePolicyId>
+            <SourcePolicyType>IAM Policy</SourcePolicyType>
+          </member>
+        </MatchedStatements>
+        <MissingContextValues/>
+        <EvalActionName>ec2:DescribeInstances</EvalActionName>
+      </member>
+      <member>
+        <EvalResourceName>*</EvalResourceName>
+        <EvalDecision>implicitDeny</EvalDecision>
+        <MatchedStatements/>
+        <ResourceSpecificResults>
+          <member>
+            <EvalResourceName>*</EvalResourceName>
+            <EvalResourceDecision>allowed</EvalResourceDecision>
+          </member>
+        </ResourceSpecificResults>
+        <EvalActionName>rds:DescribeDBInstances</EvalActionName>
+      </member>
+    </EvaluationResults>
+  </SimulatePrincipalPolicyResult>
+  <ResponseMetadata>
+    <RequestId>00000000-0000-0000-0000-000000000000</RequestId>
+  </ResponseMetadata>
+</SimulatePrincipalPolicyResponse>"#;
+        assert_eq!(
+            parse_simulation(xml).unwrap(),
+            Simulation {
+                decisions: vec![
+                    ("ec2:DescribeInstances".to_string(), "allowed".to_string()),
+                    (
+                        "rds:DescribeDBInstances".to_string(),
+                        "implicitDeny".to_string()
+                    ),
+                ],
+                marker: Some("page+2&more".to_string()),
+            }
+        );
+
+        let last = xml.replace(
+            "<IsTruncated>true</IsTruncated>",
+            "<IsTruncated>false</IsTruncated>",
+        );
+        assert_eq!(parse_simulation(&last).unwrap().marker, None);
+        let undecided = xml.replace("<EvalDecision>allowed</EvalDecision>", "");
+        assert!(parse_simulation(&undecided).is_err());
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let xml = r#"<ErrorResponse xmlns="https://iam.amazonaws.com/doc/2010-05-08/">
+  <Error>
+    <Type>Sender</Type>
+    <Code>AccessDenied</Code>
+    <Message>User: arn:aws:iam::123456789012:user/a&amp;b is not authorized to perform: iam:SimulatePrincipalPolicy</Message>
+  </Error>
+  <RequestId>00000000-0000-0000-0000-000000000000</RequestId>
+</ErrorResponse>"#;
+        let (code, message) = parse_error(xml).unwrap();
+        assert_eq!(code, "AccessDenied");
+        assert!(message.starts_with("User: arn:aws:iam::123456789012:user/a&b is"));
+    }
+}
@@ -0,0 +1,146 @@
+use std::{fs::File, io::BufReader, path::Path, time::SystemTime};
+
+use anyhow::{bail, Context};
+use aws_smithy_types::date_time::{DateTime, Format};
+use serde::{Deserialize, Serialize};
+
+use crate::security::SecurityGroups;
+
+const VERSION: u32 = 3;
+/// Oldest version still read, the fields added since have defaults.
+const MIN_VERSION: u32 = 2;
+
+/// A saved scan, so the analysis can run offline on a fixed set of groups.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Inventory {
+    pub version: u32,
+    /// RFC 3339 time the scan finished.
+    pub scanned_at: String,
+    pub groups: SecurityGroups,
+    /// Accounts the scan could not reach, missing from `groups`.
+    #[serde(default)]
+    pub failed_accounts: Vec<String>,
+}
+
+impl Inventory {
+    pub fn new(groups: SecurityGroups, failed_accounts: Vec<String>) -> Self {
+        Self {
+            version: VERSION,
+            scanned_at: DateTime::from(SystemTime::now())
+                .fmt(Format::DateTime)
+                .unwrap_or_default(),
+            groups,
+            failed_accounts,
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let file =
+            File::create(path).with_context(|| format!("failed to create {}", path.display()))?;
+        serde_json::to_writer_pretty(file, self)
+            .with_context(|| format!("failed to write {}", path.display()))?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let file =
+            File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+        let inventory: Self = serde_json::from_reader(BufReader::new(file))
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        if !(MIN_VERSION..=VERSION).contains(&inventory.version) {
+            bail!(
+                "{} has inventory version {}, expected {} to {}",
+                path.display(),
+                inventory.version,
+                MIN_VERSION,
+                VERSION
+            );
+        }
+        Ok(inventory)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use maplit::hashmap;
+
+    use super::Inventory;
+    use crate::check::{check, Allowlist, Outcome, Thresholds};
+    use crate::security::{Coverage, ExistingGroup, Rule, RulePeer, SecurityGroups};
+
+    #[test]
+    fn test_roundtrip() {
+        let mut groups = SecurityGroups {
+            external_references: hashmap![
+                "sg-1".to_string() => vec!["ec2@eu-west-1".into()],
+            ],
+            existing_groups: vec![ExistingGroup {
+                region: "eu-west-1".to_string(),
+                group_id: "sg-1".to_string(),
+                vpc_id: Some("vpc-1".to_string()),
+                group_name: "web".to_string(),
+                ingress: vec![Rule {
+                    protocol: "tcp".to_string(),
+                    from_port: Some(443),
+                    to_port: Some(443),
+                    peer: RulePeer::Group {
+                        group_id: "sg-2".to_string(),
+                        user_id: None,
+                    },
+                    description: Some("lb".to_string()),
+                }],
+                references: ["sg-2".to_string()].into_iter().collect(),
+                ..ExistingGroup::default()
+            }],
+            ..SecurityGroups::default()
+        };
+        groups.record_coverage(
+            "1",
+            "eu-west-1",
+            "rds",
+            Coverage::Failed("denied".to_string()),
+        );
+
+        let json = serde_json::to_string(&Inventory::new(groups.clone(), vec![])).unwrap();
+        let inventory: Inventory = serde_json::from_str(&json).unwrap();
+        assert_eq!(inventory.groups.existing_groups, groups.existing_groups);
+        assert_eq!(
+            inventory.groups.external_references,
+            groups.external_references
+        );
+        assert_eq!(inventory.groups.coverage, groups.coverage);
+        assert!(!inventory.scanned_at.is_empty());
+    }
+
+    #[test]
+    fn test_failed_accounts() {
+        let mut groups = SecurityGroups::default();
+        groups.record_coverage("1", "eu-west-1", "ec2", Coverage::Complete);
+        let path = std::env::temp_dir().join(format!("inventory-{}.json", std::process::id()));
+        Inventory::new(groups, vec!["2".to_string()])
+            .save(&path)
+            .unwrap();
+        let inventory = Inventory::load(&path).unwrap();
+        assert_eq!(inventory.failed_accounts, ["2"]);
+
+        let result = check(
+            &inventory.groups,
+            &Thresholds {
+                max_unused: 0,
+                ..Thresholds::default()
+            },
+            &Allowlist::default(),
+            &inventory.failed_accounts,
+        );
+        assert_eq!(result.outcome(), Outcome::ScanIncomplete);
+
+        // Version 2 files predate the field.
+        let mut json = serde_json::to_value(&inventory).unwrap();
+        json["version"] = 2.into();
+        json.as_object_mut().unwrap().remove("failed_accounts");
+        std::fs::write(&path, json.to_string()).unwrap();
+        let inventory = Inventory::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(inventory.failed_accounts.is_empty());
+    }
+}
@@ -1,43 +1,43 @@
 use async_trait::async_trait;
-use aws_sdk_lambda::Client;
-use aws_types::SdkConfig;
-use futures::StreamExt;
-use itertools::Itertools;
 
-use crate::security::{SecurityGroups, SecurityGroupsProvider};
+use crate::backend::Backend;
+use crate::error::ProviderError;
+use crate::provider::SecurityGroupsProvider;
+use crate::security::SecurityGroups;
 
 pub struct LambdaGroups {}
 
 #[async_trait]
-impl SecurityGroupsProvider<SdkConfig> for LambdaGroups {
-    async fn load(config: &SdkConfig) -> SecurityGroups {
-        let client = Client::new(config);
-        let group_ids = client
+impl SecurityGroupsProvider for LambdaGroups {
+    fn name(&self) -> &'static str {
+        "lambda"
+    }
+
+    fn description(&self) -> &'static str {
+        "Lambda functions attached to a VPC"
+    }
+
+    fn iam_actions(&self) -> &'static [&'static str] {
+        &["lambda:ListFunctions"]
+    }
+
+    async fn load(&self, backend: &dyn Backend) -> Result<SecurityGroups, ProviderError> {
+        let region = backend.region();
+        let functions = backend
             .list_functions()
-            .into_paginator()
-            .items()
-            .send()
-            .flat_map(|item| {
-                let groups = item
-                    .unwrap()
-                    .vpc_config()
-                    .map(|vpc_config| {
-                        vpc_config
-                            .security_group_ids()
-                            .unwrap_or_default()
-                            .iter()
-                            .map(|group_id| group_id.to_owned())
-                            .collect_vec()
-                    })
-                    .unwrap_or_default();
-                futures::stream::iter(groups)
-            })
-            .collect::<Vec<_>>()
-            .await;
-
-        SecurityGroups::create_from_group_ids(
-            format!("lambda@{}", config.region().unwrap()),
-            group_ids.into_iter(),
-        )
+            .await
+            .map_err(|err| ProviderError::new(self.name(), &region, err))?;
+
+        let resources = functions.into_iter().map(|function| {
+            (
+                format!("function {}", function.function_name),
+                function.security_groups,
+            )
+        });
+
+        Ok(SecurityGroups::create_from_resources(
+            format!("{}@{}", self.name(), region),
+            resources,
+        ))
     }
 }
@@ -0,0 +1,49 @@
+//! Finds AWS security groups that no service references, directly or through the
+//! rules of other groups, and removes them.
+//!
+//! [`Scanner`] builds the inventory, [`SecurityGroups`] answers questions about it.
+
+pub mod accounts;
+#[cfg(feature = "alb")]
+pub mod alb;
+pub mod backend;
+pub mod cache;
+pub mod check;
+pub mod cleanup;
+pub mod client;
+pub mod config;
+#[cfg(feature = "ec2")]
+pub mod ec2;
+#[cfg(feature = "elasticache")]
+pub mod elasticache;
+pub mod error;
+pub mod explain;
+#[cfg(feature = "test-util")]
+pub mod fake;
+pub mod graph;
+pub mod iam;
+pub mod inventory;
+#[cfg(feature = "lambda")]
+pub mod lambda;
+pub mod output;
+pub mod permissions;
+pub mod plan;
+pub mod protection;
+pub mod provider;
+#[cfg(feature = "rds")]
+pub mod rds;
+pub mod regions;
+pub mod report;
+pub mod scan;
+pub mod scanner;
+pub mod security;
+mod signing;
+pub mod throttle;
+#[cfg(feature = "tui")]
+pub mod tui;
+mod utils;
+
+pub use provider::{Registry, SecurityGroupsProvider};
+pub use scan::ScanReport;
+pub use scanner::{Scanner, ScannerBuilder};
+pub use security::{ExistingGroup, SecurityGroups};
@@ -1,22 +1,32 @@
-use std::collections::HashSet;
+use std::{
+    io::{IsTerminal, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
-use aws_sdk_ec2::{types::SdkError, Region};
-use clap::Parser;
+use anyhow::{bail, Context};
+use aws_sg_cleanup::{
+    backend::AwsBackend,
+    cache::ResponseCache,
+    check, cleanup,
+    client::{ClientOptions, Endpoints},
+    config::Config,
+    explain,
+    graph::deletion_order,
+    inventory::Inventory,
+    output, permissions,
+    plan::Plan,
+    regions::RegionFilter,
+    report,
+    throttle::ThrottleConfig,
+    ExistingGroup, Registry, ScanReport, Scanner, SecurityGroups,
+};
+use clap::{ArgEnum, Args, Parser, Subcommand};
 use cli_table::{print_stdout, Cell, Style, Table};
 use itertools::Itertools;
 use log::{info, warn};
 use rand::Rng;
-use security::{SecurityGroups, SecurityGroupsProvider};
 use sha1::{Digest, Sha1};
-use utils::load_regions;
-
-mod alb;
-mod ec2;
-mod elasticache;
-mod lambda;
-mod rds;
-mod security;
-mod utils;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -29,10 +39,200 @@ async fn main() -> anyhow::Result<()> {
         .format_timestamp(None)
         .init();
 
-    match args {
-        Cli::Print => print_unused().await?,
-        Cli::Clean => clean_unused().await?,
-        Cli::MakeNoise => make_noise().await?,
+    if let Command::Clean { yes: false, .. } = args.command {
+        if !std::io::stdin().is_terminal() {
+            bail!("clean asks for confirmation, pass --yes when stdin is not a terminal");
+        }
+    }
+
+    let config = Config::discover(args.config.as_deref())?;
+    let options = ClientOptions {
+        endpoints: args.endpoints.load()?,
+        profile: args.accounts.profile,
+        ..ClientOptions::default()
+    };
+    let cache = match args.command {
+        Command::Clean { .. } | Command::Apply { .. } => {
+            if args.cache.enabled() {
+                warn!("deleting always scans live, ignoring the response cache");
+            }
+            None
+        }
+        _ => args.cache.load(),
+    };
+    let usage = permissions::Usage {
+        clean: false,
+        preflight: args.preflight,
+        assume_role: args.accounts.role_arn.is_some(),
+        organization: args.accounts.organization,
+    };
+    let mut scanner = Scanner::builder()
+        .client_options(options)
+        .providers(or_config(args.providers.providers, &config.providers))
+        .skip_providers(or_config(
+            args.providers.skip_providers,
+            &config.skip_providers,
+        ))
+        .region_filter(args.regions.into_filter(&config))
+        .protection(config.protection()?)
+        .throttle(args.throttle.into());
+    if let Some(role_arn) = args.accounts.role_arn {
+        scanner = scanner.assume_role(role_arn, args.accounts.external_id);
+    }
+    if args.accounts.organization {
+        scanner = scanner.organization(args.accounts.organization_role);
+    }
+    if let Some(cache) = cache {
+        scanner = scanner.cache(cache);
+    }
+    let scanner = scanner.build()?;
+
+    if args.preflight {
+        match &args.command {
+            Command::Scan { .. }
+            | Command::Print { from: None, .. }
+            | Command::Report { from: None, .. }
+            | Command::Why { from: None, .. }
+            | Command::Plan { from: None, .. }
+            | Command::Check { from: None, .. } => preflight(&scanner, usage).await?,
+            #[cfg(feature = "tui")]
+            Command::Tui { from: None, .. } => preflight(&scanner, usage).await?,
+            Command::Clean { .. } | Command::Apply { .. } => {
+                let usage = permissions::Usage {
+                    clean: true,
+                    ..usage
+                };
+                preflight(&scanner, usage).await?
+            }
+            Command::Permissions { scan_only } => {
+                let usage = permissions::Usage {
+                    clean: !scan_only,
+                    ..usage
+                };
+                preflight(&scanner, usage).await?
+            }
+            _ => {}
+        }
+    }
+
+    match args.command {
+        Command::Scan { output } => scan(&scanner, &output).await?,
+        Command::Print {
+            rules,
+            from,
+            format,
+        } => {
+            let format = match format {
+                Some(format) => format,
+                None => config_format(config.output.format.as_deref(), PrintFormat::Table)?,
+            };
+            let groups = load_inventory(&scanner, from.as_deref()).await?.groups;
+            match format.into() {
+                Some(format) => output::write(&groups, format, std::io::stdout().lock())?,
+                None => print_unused(&groups, rules)?,
+            }
+        }
+        Command::Report {
+            format,
+            from,
+            output,
+        } => {
+            let format = match format {
+                Some(format) => format,
+                None => config_format(config.output.report_format.as_deref(), ReportFormat::Html)?,
+            };
+            let inventory = load_inventory(&scanner, from.as_deref()).await?;
+            let title = format!("Security groups scanned at {}", inventory.scanned_at);
+            let report = report::render(&inventory.groups, format.into(), &title);
+            match output {
+                Some(path) => {
+                    std::fs::write(&path, report)
+                        .with_context(|| format!("failed to write {}", path.display()))?;
+                    info!("saved {}", path.display());
+                }
+                None => print!("{}", report),
+            }
+        }
+        Command::Why {
+            group_id,
+            from,
+            max_paths,
+        } => {
+            let groups = load_inventory(&scanner, from.as_deref()).await?.groups;
+            explain_group(&groups, &group_id, max_paths)?;
+        }
+        Command::Clean {
+            allow_incomplete_coverage,
+            interactive,
+            yes,
+        } => {
+            let confirmation = match (yes, interactive) {
+                (true, _) => Confirmation::Skip,
+                (false, true) => Confirmation::EachGroup,
+                (false, false) => Confirmation::Region,
+            };
+            clean_unused(&scanner, allow_incomplete_coverage, confirmation).await?
+        }
+        Command::Plan {
+            out,
+            from,
+            allow_incomplete_coverage,
+        } => {
+            let groups = load_inventory(&scanner, from.as_deref()).await?.groups;
+            let plan = Plan::new(&groups, allow_incomplete_coverage);
+            print_plan(&plan)?;
+            plan.save(&out)?;
+            info!("saved {} with {} groups", out.display(), plan.groups.len());
+        }
+        Command::Apply { plan } => apply_plan(&scanner, &plan).await?,
+        Command::Check {
+            max_unused,
+            region_max_unused,
+            allow,
+            allowlist,
+            from,
+        } => {
+            let mut thresholds = check::Thresholds {
+                max_unused,
+                ..check::Thresholds::default()
+            };
+            for item in region_max_unused {
+                let (region, limit) = item
+                    .split_once('=')
+                    .ok_or_else(|| anyhow::anyhow!("expected REGION=N, got {}", item))?;
+                let limit = limit
+                    .parse()
+                    .with_context(|| format!("invalid limit for {}", region))?;
+                thresholds.regions.insert(region.to_owned(), limit);
+            }
+            let mut allowed = check::Allowlist::new(allow);
+            if let Some(path) = allowlist {
+                allowed.extend_from_file(&path)?;
+            }
+            let inventory = load_inventory(&scanner, from.as_deref()).await?;
+            let outcome = print_check(&check::check(
+                &inventory.groups,
+                &thresholds,
+                &allowed,
+                &inventory.failed_accounts,
+            ))?;
+            std::process::exit(outcome.exit_code());
+        }
+        #[cfg(feature = "tui")]
+        Command::Tui { from, out } => {
+            let groups = load_inventory(&scanner, from.as_deref()).await?.groups;
+            aws_sg_cleanup::tui::run(&groups, out)?;
+        }
+        Command::MakeNoise => make_noise(&scanner).await?,
+        Command::Providers => print_providers(&scanner)?,
+        Command::Permissions { scan_only } => {
+            let usage = permissions::Usage {
+                clean: !scan_only,
+                ..usage
+            };
+            let policy = permissions::policy(scanner.providers(), usage);
+            println!("{}", serde_json::to_string_pretty(&policy)?);
+        }
     }
 
     Ok(())
@@ -40,54 +240,438 @@ async fn main() -> anyhow::Result<()> {
 
 #[derive(Parser)]
 #[clap(name = "aws-sg-cleanup", bin_name = "aws-sg-cleanup")]
-enum Cli {
+struct Cli {
+    #[clap(
+        long,
+        global = true,
+        help = "Config file, defaults to .aws-sg-cleanup.toml when present"
+    )]
+    config: Option<PathBuf>,
+    #[clap(
+        long,
+        global = true,
+        help = "Check IAM permissions with iam:SimulatePrincipalPolicy before running. Only the \
+                scanning principal is checked, the role of --role-arn when set, not the roles \
+                assumed in member accounts with --organization"
+    )]
+    preflight: bool,
+    #[clap(flatten)]
+    throttle: ThrottleArgs,
+    #[clap(flatten)]
+    cache: CacheArgs,
+    #[clap(flatten)]
+    endpoints: EndpointArgs,
+    #[clap(flatten)]
+    providers: ProviderArgs,
+    #[clap(flatten)]
+    regions: RegionArgs,
+    #[clap(flatten)]
+    accounts: AccountArgs,
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Args)]
+struct ThrottleArgs {
+    #[clap(
+        long,
+        global = true,
+        default_value_t = 16,
+        help = "Maximum number of concurrent provider scans"
+    )]
+    max_concurrency: usize,
+    #[clap(
+        long,
+        global = true,
+        default_value_t = 10.0,
+        help = "Request rate limit per service and region, counting every page of a listing"
+    )]
+    requests_per_second: f64,
+    #[clap(
+        long,
+        global = true,
+        default_value_t = 5,
+        help = "Retries with jittered backoff for each throttled request"
+    )]
+    max_retries: u32,
+}
+
+impl From<ThrottleArgs> for ThrottleConfig {
+    fn from(args: ThrottleArgs) -> Self {
+        Self {
+            max_concurrency: args.max_concurrency,
+            requests_per_second: args.requests_per_second,
+            max_retries: args.max_retries,
+        }
+    }
+}
+
+#[derive(Args)]
+struct CacheArgs {
+    #[clap(
+        long,
+        global = true,
+        help = "Cache provider responses on disk between runs"
+    )]
+    cache: bool,
+    #[clap(
+        long,
+        global = true,
+        default_value_t = 300,
+        help = "Seconds a cached response stays valid"
+    )]
+    cache_ttl: u64,
+    #[clap(
+        long,
+        global = true,
+        help = "Ignore cached responses, scan again and update the cache"
+    )]
+    refresh: bool,
+    #[clap(
+        long,
+        global = true,
+        help = "Cache directory [default: ~/.cache/aws-sg-cleanup]"
+    )]
+    cache_dir: Option<PathBuf>,
+}
+
+impl CacheArgs {
+    fn enabled(&self) -> bool {
+        self.cache || self.refresh
+    }
+
+    fn load(self) -> Option<ResponseCache> {
+        self.enabled().then(|| {
+            ResponseCache::new(
+                self.cache_dir.unwrap_or_else(ResponseCache::default_dir),
+                Duration::from_secs(self.cache_ttl),
+                self.refresh,
+            )
+        })
+    }
+}
+
+#[derive(Args)]
+struct ProviderArgs {
+    #[clap(
+        long,
+        global = true,
+        use_value_delimiter = true,
+        value_name = "PROVIDER",
+        help = "Only run these providers, see the providers command. Regions are incomplete \
+                without every provider"
+    )]
+    providers: Vec<String>,
+    #[clap(
+        long,
+        global = true,
+        use_value_delimiter = true,
+        value_name = "PROVIDER",
+        help = "Do not run these providers, leaving coverage of every region incomplete"
+    )]
+    skip_providers: Vec<String>,
+}
+
+#[derive(Args)]
+struct RegionArgs {
+    #[clap(
+        long = "region",
+        global = true,
+        value_name = "GLOB",
+        help = "Only scan regions matching this pattern, e.g. eu-*; repeatable"
+    )]
+    regions: Vec<String>,
+    #[clap(
+        long = "exclude-region",
+        global = true,
+        value_name = "GLOB",
+        help = "Do not scan regions matching this pattern; repeatable"
+    )]
+    exclude_regions: Vec<String>,
+    #[clap(
+        long,
+        global = true,
+        help = "Skip opt-in regions even when they are enabled for the account"
+    )]
+    skip_opt_in_regions: bool,
+}
+
+impl RegionArgs {
+    fn into_filter(self, config: &Config) -> RegionFilter {
+        RegionFilter {
+            include: or_config(self.regions, &config.regions),
+            exclude: or_config(self.exclude_regions, &config.exclude_regions),
+            skip_opt_in: self.skip_opt_in_regions || config.skip_opt_in_regions,
+        }
+    }
+}
+
+/// Arguments given on the command line replace the configured list.
+fn or_config(args: Vec<String>, config: &[String]) -> Vec<String> {
+    match args.is_empty() {
+        true => config.to_vec(),
+        false => args,
+    }
+}
+
+fn config_format<T: ArgEnum>(value: Option<&str>, default: T) -> anyhow::Result<T> {
+    match value {
+        Some(value) => T::from_str(value, true)
+            .map_err(|err| anyhow::anyhow!("invalid output format in config: {}", err)),
+        None => Ok(default),
+    }
+}
+
+#[derive(Args)]
+struct AccountArgs {
+    #[clap(
+        long,
+        global = true,
+        help = "Shared config profile, defaults to AWS_PROFILE"
+    )]
+    profile: Option<String>,
+    #[clap(
+        long,
+        global = true,
+        help = "Role to assume before scanning or cleaning"
+    )]
+    role_arn: Option<String>,
+    #[clap(
+        long,
+        global = true,
+        requires = "role-arn",
+        help = "External ID for assuming --role-arn"
+    )]
+    external_id: Option<String>,
+    #[clap(
+        long,
+        global = true,
+        help = "Scan every active account of the organization"
+    )]
+    organization: bool,
+    #[clap(
+        long,
+        global = true,
+        default_value = "OrganizationAccountAccessRole",
+        help = "Role to assume in the member accounts with --organization"
+    )]
+    organization_role: String,
+}
+
+#[derive(Args)]
+struct EndpointArgs {
+    #[clap(
+        long,
+        global = true,
+        help = "Endpoint URL for every service, defaults to AWS_ENDPOINT_URL"
+    )]
+    endpoint_url: Option<String>,
+    #[clap(
+        long,
+        global = true,
+        value_name = "SERVICE=URL",
+        help = "Endpoint URL for one service, defaults to AWS_ENDPOINT_URL_<SERVICE>"
+    )]
+    service_endpoint: Vec<String>,
+}
+
+impl EndpointArgs {
+    fn load(self) -> anyhow::Result<Endpoints> {
+        let mut endpoints = Endpoints::from_env()?;
+        if let Some(url) = self.endpoint_url {
+            endpoints.set_global(&url)?;
+        }
+        for item in self.service_endpoint {
+            let (service, url) = item
+                .split_once('=')
+                .ok_or_else(|| anyhow::anyhow!("expected SERVICE=URL, got {}", item))?;
+            endpoints.set_service(service, url)?;
+        }
+        Ok(endpoints)
+    }
+}
+
+#[derive(Subcommand)]
+enum Command {
     #[clap(about = "Print all security groups in all regions and services referencing them")]
-    Print,
+    Print {
+        #[clap(
+            long,
+            help = "Also print ingress and egress rules of every group, table format only"
+        )]
+        rules: bool,
+        #[clap(long, help = "Read groups from an inventory file instead of scanning")]
+        from: Option<PathBuf>,
+        #[clap(
+            long,
+            arg_enum,
+            help = "Output format, all but table follow a versioned schema [default: table]"
+        )]
+        format: Option<PrintFormat>,
+    },
+    #[clap(about = "Write an HTML or Markdown report with summaries and coverage gaps")]
+    Report {
+        #[clap(long, arg_enum, help = "Report format [default: html]")]
+        format: Option<ReportFormat>,
+        #[clap(long, help = "Read groups from an inventory file instead of scanning")]
+        from: Option<PathBuf>,
+        #[clap(long, short, help = "File to write, stdout by default")]
+        output: Option<PathBuf>,
+    },
+    #[clap(about = "Print every path from a group to the services referencing it")]
+    Why {
+        #[clap(help = "ID of the group to explain")]
+        group_id: String,
+        #[clap(long, help = "Read groups from an inventory file instead of scanning")]
+        from: Option<PathBuf>,
+        #[clap(
+            long,
+            default_value_t = 50,
+            help = "Stop after this many paths, shortest first"
+        )]
+        max_paths: usize,
+    },
+    #[clap(about = "Scan all regions and save the inventory to a file")]
+    Scan {
+        #[clap(long, short, help = "Inventory file to write")]
+        output: PathBuf,
+    },
     #[clap(about = "Delete unused security groups in all regions")]
-    Clean,
+    Clean {
+        #[clap(
+            long,
+            help = "Also clean regions where some providers failed to load references"
+        )]
+        allow_incomplete_coverage: bool,
+        #[clap(
+            long,
+            short,
+            conflicts_with = "yes",
+            help = "Confirm all, none or each group of every region"
+        )]
+        interactive: bool,
+        #[clap(
+            long,
+            short,
+            help = "Delete without asking, required when stdin is not a TTY"
+        )]
+        yes: bool,
+    },
+    #[clap(about = "Save the unused groups to a plan file for review before apply")]
+    Plan {
+        #[clap(long, help = "Plan file to write")]
+        out: PathBuf,
+        #[clap(long, help = "Read groups from an inventory file instead of scanning")]
+        from: Option<PathBuf>,
+        #[clap(
+            long,
+            help = "Also plan regions where some providers failed to load references"
+        )]
+        allow_incomplete_coverage: bool,
+    },
+    #[clap(about = "Delete the groups of a plan, refusing any group changed since")]
+    Apply {
+        #[clap(help = "Plan file written by plan")]
+        plan: PathBuf,
+    },
+    #[cfg(feature = "tui")]
+    #[clap(about = "Browse groups and their references, and mark groups for a plan")]
+    Tui {
+        #[clap(long, help = "Read groups from an inventory file instead of scanning")]
+        from: Option<PathBuf>,
+        #[clap(long, help = "Plan file to write with the marked groups")]
+        out: Option<PathBuf>,
+    },
+    #[clap(
+        about = "Check the number of unused groups against thresholds, deleting nothing",
+        long_about = "Check the number of unused groups against thresholds, deleting nothing.\n\n\
+                      Exits with 0 when ok, 2 when a threshold is exceeded and 3 when some \
+                      regions or accounts could not be scanned. Errors exit with 1."
+    )]
+    Check {
+        #[clap(long, help = "Maximum number of unused groups in all regions")]
+        max_unused: usize,
+        #[clap(
+            long,
+            value_name = "REGION=N",
+            help = "Maximum number of unused groups in one region; repeatable"
+        )]
+        region_max_unused: Vec<String>,
+        #[clap(
+            long,
+            value_name = "GROUP",
+            help = "Group ID or name that does not count; repeatable"
+        )]
+        allow: Vec<String>,
+        #[clap(long, help = "File with one allowed group ID or name per line")]
+        allowlist: Option<PathBuf>,
+        #[clap(long, help = "Read groups from an inventory file instead of scanning")]
+        from: Option<PathBuf>,
+    },
     #[clap(about = "Create 20 empty security groups in default region")]
     MakeNoise,
+    #[clap(about = "List the providers of this build and the IAM actions they need")]
+    Providers,
+    #[clap(
+        about = "Print the minimal IAM policy for the enabled providers and clean",
+        long_about = "Print the minimal IAM policy for the enabled providers and clean.\n\n\
+                      Includes the actions --preflight, --role-arn and --organization need when \
+                      given. With --role-arn, sts:AssumeRole is in its own statement for the \
+                      credentials assuming the role."
+    )]
+    Permissions {
+        #[clap(long, help = "Leave out the actions only clean needs")]
+        scan_only: bool,
+    },
 }
 
-async fn load_groups() -> anyhow::Result<SecurityGroups> {
-    let regions = load_regions().await?.map(load_region);
-
-    let groups = futures::future::join_all(regions).await.into_iter().fold(
-        SecurityGroups::default(),
-        |mut acc, item| {
-            acc.merge(&item);
-            acc
-        },
-    );
+#[derive(Clone, Copy, ArgEnum)]
+enum PrintFormat {
+    Table,
+    Json,
+    Jsonl,
+    Csv,
+    Yaml,
+}
 
-    Ok(groups)
+impl From<PrintFormat> for Option<output::Format> {
+    fn from(format: PrintFormat) -> Self {
+        match format {
+            PrintFormat::Table => None,
+            PrintFormat::Json => Some(output::Format::Json),
+            PrintFormat::Jsonl => Some(output::Format::Jsonl),
+            PrintFormat::Csv => Some(output::Format::Csv),
+            PrintFormat::Yaml => Some(output::Format::Yaml),
+        }
+    }
 }
 
-async fn load_region(region: Region) -> SecurityGroups {
-    let sdk_config = aws_config::from_env().region(region.clone()).load().await;
+#[derive(Clone, Copy, ArgEnum)]
+enum ReportFormat {
+    Html,
+    Markdown,
+}
 
-    let res = futures::future::join_all(vec![
-        ec2::EC2Groups::load(&sdk_config),
-        alb::ALBGroups::load(&sdk_config),
-        elasticache::ElasticacheGroups::load(&sdk_config),
-        lambda::LambdaGroups::load(&sdk_config),
-        rds::RDSGroups::load(&sdk_config),
-        security::AWSSecurityGroups::load(&sdk_config),
-    ])
-    .await
-    .into_iter()
-    .fold(SecurityGroups::default(), |mut acc, item| {
-        acc.merge(&item);
-        acc
-    });
+impl From<ReportFormat> for report::Format {
+    fn from(format: ReportFormat) -> Self {
+        match format {
+            ReportFormat::Html => Self::Html,
+            ReportFormat::Markdown => Self::Markdown,
+        }
+    }
+}
 
-    info!("{} loaded", region);
-    res
+async fn load_groups(scanner: &Scanner) -> anyhow::Result<ScanReport> {
+    let report = scanner.scan().await?;
+    report.log_failures();
+    Ok(report)
 }
 
-async fn make_noise() -> anyhow::Result<()> {
-    let config = aws_config::load_from_env().await;
-    let client = aws_sdk_ec2::Client::new(&config);
+async fn make_noise(scanner: &Scanner) -> anyhow::Result<()> {
+    let config = scanner
+        .client_config(scanner.client_options().default_region())
+        .await;
+    let client = config.ec2();
     for _ in 0..20 {
         let rand_string = rand::thread_rng()
             .sample_iter(rand::distributions::Standard)
@@ -110,71 +694,482 @@ async fn make_noise() -> anyhow::Result<()> {
     Ok(())
 }
 
-async fn print_unused() -> anyhow::Result<()> {
-    let groups = load_groups().await?;
+async fn load_inventory(scanner: &Scanner, from: Option<&Path>) -> anyhow::Result<Inventory> {
+    match from {
+        Some(path) => {
+            let mut inventory = Inventory::load(path)?;
+            info!(
+                "loaded {} scanned at {}",
+                path.display(),
+                inventory.scanned_at
+            );
+            let filter = scanner.region_filter();
+            inventory
+                .groups
+                .retain_regions(|region| filter.matches(region));
+            inventory.groups.protection = scanner.protection().clone();
+            Ok(inventory)
+        }
+        None => {
+            let report = load_groups(scanner).await?;
+            Ok(Inventory::new(report.groups, report.failed_accounts))
+        }
+    }
+}
+
+async fn scan(scanner: &Scanner, output: &Path) -> anyhow::Result<()> {
+    let ScanReport {
+        groups,
+        failed_accounts,
+        ..
+    } = load_groups(scanner).await?;
+    Inventory::new(groups, failed_accounts).save(output)?;
+    info!("saved {}", output.display());
+    Ok(())
+}
+
+fn print_unused(groups: &SecurityGroups, rules: bool) -> anyhow::Result<()> {
+    let graph = groups.reference_graph();
     let rows = groups
         .existing_groups
         .iter()
-        .sorted_by_key(|x| x.region.clone())
+        .sorted_by_key(|x| (x.account_id.clone(), x.region.clone()))
         .map(|group| {
-            let group_id = group.group_id.clone();
-            let refs = groups
-                .collect_referencing_services(&group_id, HashSet::new())
-                .iter()
+            let refs = graph
+                .referencing_services(&group.group_id)
+                .into_iter()
+                .sorted()
+                .map(|reference| match reference.account_id == group.account_id {
+                    true => reference.source.clone(),
+                    false => reference.to_string(),
+                })
                 .join(", ");
 
-            let bold = refs.is_empty();
+            let complete = groups.is_region_complete(&group.account_id, &group.region);
+            let protected = groups.protection_reason(group);
+            let bold = refs.is_empty() && complete && !group.is_default && protected.is_none();
+            let notes = itertools::chain!(
+                group
+                    .is_default
+                    .then(|| "default group, not deletable".to_string()),
+                protected.map(|reason| format!("protected: {}", reason)),
+                (!complete).then(|| "coverage incomplete".to_string()),
+            )
+            .join(", ");
+            let tags = group
+                .tags
+                .iter()
+                .map(|(key, value)| format!("{}={}", key, value))
+                .join("\n");
             vec![
+                group.account_id.clone().cell(),
                 group.region.clone().cell().bold(bold),
                 group.group_id.clone().cell().bold(bold),
                 group.group_name.clone().cell().bold(bold),
+                group.vpc_id.clone().unwrap_or_default().cell(),
+                group.owner_id.clone().unwrap_or_default().cell(),
+                tags.cell(),
                 refs.cell(),
+                notes.cell(),
             ]
         })
         .collect_vec();
     if !rows.is_empty() {
         let table = rows.table().title(vec![
+            "Account".cell().bold(true),
             "Region".cell().bold(true),
             "Group ID".cell().bold(true),
             "Group Name".cell().bold(true),
+            "VPC".cell().bold(true),
+            "Owner".cell().bold(true),
+            "Tags".cell().bold(true),
             "References".cell().bold(true),
+            "Notes".cell().bold(true),
+        ]);
+        print_stdout(table)?;
+    }
+
+    if rules {
+        print_rules(groups)?;
+    }
+
+    let dangling = groups
+        .dangling_references
+        .iter()
+        .sorted_by_key(|x| (x.region.clone(), x.group_name.clone()))
+        .map(|reference| {
+            vec![
+                reference.region.clone().cell(),
+                reference.vpc_id.clone().unwrap_or_default().cell(),
+                reference.group_name.clone().cell(),
+                reference.source.clone().cell(),
+            ]
+        })
+        .collect_vec();
+    if !dangling.is_empty() {
+        let table = dangling.table().title(vec![
+            "Region".cell().bold(true),
+            "VPC".cell().bold(true),
+            "Dangling Group Name".cell().bold(true),
+            "Referenced By".cell().bold(true),
         ]);
         print_stdout(table)?;
     }
     Ok(())
 }
 
-async fn clean_unused() -> anyhow::Result<()> {
-    let groups = load_groups().await?;
-    for (region, unused_groups) in groups
-        .find_unused()
-        .into_iter()
-        .group_by(|x| x.region.clone())
+fn explain_group(groups: &SecurityGroups, group_id: &str, max_paths: usize) -> anyhow::Result<()> {
+    let group = groups
+        .existing_groups
+        .iter()
+        .find(|x| x.group_id == group_id)
+        .with_context(|| format!("{} is not in the scanned regions", group_id))?;
+    println!(
+        "{} ({}) in {}/{}",
+        group.group_id, group.group_name, group.account_id, group.region
+    );
+    if !groups.is_region_complete(&group.account_id, &group.region) {
+        println!("warning: some providers failed in this region, paths may be missing");
+    }
+
+    let explain::ReferencePaths { paths, truncated } =
+        explain::reference_paths(groups, group_id, max_paths);
+    if paths.is_empty() && !truncated {
+        if group.is_default {
+            println!("no service references it, but default groups cannot be deleted");
+        } else if let Some(reason) = groups.protection_reason(group) {
+            println!("no service references it, but it is protected: {}", reason);
+        } else {
+            println!("no service references it, it is unused");
+        }
+        return Ok(());
+    }
+    for (i, path) in paths.iter().enumerate() {
+        println!();
+        for (j, line) in path.describe(group_id).into_iter().enumerate() {
+            match j {
+                0 => println!("{:>3}. {}", i + 1, line),
+                _ => println!("     {}", line),
+            }
+        }
+    }
+    if truncated {
+        println!();
+        println!("stopped after {} paths, see --max-paths", max_paths);
+    }
+    Ok(())
+}
+
+fn print_check(result: &check::CheckResult<'_>) -> anyhow::Result<check::Outcome> {
+    let table = result
+        .regions
+        .iter()
+        .map(|region| {
+            let status = match (region.complete, region.exceeded()) {
+                (false, _) => "incomplete",
+                (true, true) => "exceeded",
+                (true, false) => "ok",
+            };
+            vec![
+                region.account_id.cell(),
+                region.region.cell(),
+                region.unused.len().cell(),
+                region.allowed.cell(),
+                region
+                    .max_unused
+                    .map(|x| x.to_string())
+                    .unwrap_or_default()
+                    .cell(),
+                status.cell(),
+            ]
+        })
+        .table()
+        .title(vec![
+            "Account".cell().bold(true),
+            "Region".cell().bold(true),
+            "Unused".cell().bold(true),
+            "Allowed".cell().bold(true),
+            "Limit".cell().bold(true),
+            "Status".cell().bold(true),
+        ]);
+    print_stdout(table)?;
+
+    for region in result.regions.iter().filter(|x| x.exceeded()) {
+        println!(
+            "{} of {}: {}",
+            region.region,
+            region.account_id,
+            region.unused.iter().map(|x| &x.group_id).join(", ")
+        );
+    }
+    for account_id in result.failed_accounts.iter() {
+        println!("account {} could not be scanned", account_id);
+    }
+    let outcome = result.outcome();
+    println!(
+        "{} unused groups in complete regions, limit {}: {}",
+        result.total,
+        result.max_unused,
+        match outcome {
+            check::Outcome::Ok => "ok",
+            check::Outcome::ThresholdExceeded => "threshold exceeded",
+            check::Outcome::ScanIncomplete => "scan incomplete",
+        }
+    );
+    Ok(outcome)
+}
+
+fn print_plan(plan: &Plan) -> anyhow::Result<()> {
+    let table = plan
+        .groups
+        .iter()
+        .map(|group| {
+            vec![
+                group.account_id.as_str().cell(),
+                group.region.as_str().cell(),
+                group.group_id.as_str().cell(),
+                group.group_name.as_str().cell(),
+            ]
+        })
+        .table()
+        .title(vec![
+            "Account".cell().bold(true),
+            "Region".cell().bold(true),
+            "Group ID".cell().bold(true),
+            "Name".cell().bold(true),
+        ]);
+    print_stdout(table)?;
+    Ok(())
+}
+
+fn print_rules(groups: &SecurityGroups) -> anyhow::Result<()> {
+    let rows = groups
+        .existing_groups
+        .iter()
+        .sorted_by_key(|x| (x.region.clone(), x.group_id.clone()))
+        .flat_map(|group| {
+            let ingress = group.ingress.iter().map(|rule| ("ingress", rule));
+            let egress = group.egress.iter().map(|rule| ("egress", rule));
+            ingress.chain(egress).map(|(direction, rule)| {
+                vec![
+                    group.region.clone().cell(),
+                    group.group_id.clone().cell(),
+                    direction.cell(),
+                    rule.protocol_name().cell(),
+                    rule.ports().cell(),
+                    rule.peer.to_string().cell(),
+                    rule.description.clone().unwrap_or_default().cell(),
+                ]
+            })
+        })
+        .collect_vec();
+    if !rows.is_empty() {
+        let table = rows.table().title(vec![
+            "Region".cell().bold(true),
+            "Group ID".cell().bold(true),
+            "Direction".cell().bold(true),
+            "Protocol".cell().bold(true),
+            "Ports".cell().bold(true),
+            "Peer".cell().bold(true),
+            "Description".cell().bold(true),
+        ]);
+        print_stdout(table)?;
+    }
+    Ok(())
+}
+
+async fn preflight(scanner: &Scanner, usage: permissions::Usage) -> anyhow::Result<()> {
+    let config = scanner
+        .client_config(scanner.client_options().default_region())
+        .await;
+    let missing = permissions::preflight(&config, scanner.providers(), usage).await?;
+    if missing.is_empty() {
+        info!("preflight passed");
+        return Ok(());
+    }
+    let rows = missing
+        .iter()
+        .map(|(name, actions)| vec![name.cell(), actions.join("\n").cell()])
+        .collect_vec();
+    let table = rows.table().title(vec![
+        "Provider".cell().bold(true),
+        "Missing Actions".cell().bold(true),
+    ]);
+    print_stdout(table)?;
+    anyhow::bail!(
+        "missing permissions, grant them or skip providers with --skip-providers, which leaves \
+         coverage incomplete so clean and apply need --allow-incomplete-coverage"
+    )
+}
+
+fn print_providers(scanner: &Scanner) -> anyhow::Result<()> {
+    let registry = Registry::builtin();
+    let rows = registry
+        .iter()
+        .map(|provider| {
+            let enabled = scanner
+                .providers()
+                .iter()
+                .any(|x| x.name() == provider.name());
+            vec![
+                provider.name().cell(),
+                if enabled { "yes" } else { "no" }.cell(),
+                provider.description().cell(),
+                provider.iam_actions().join("\n").cell(),
+            ]
+        })
+        .collect_vec();
+    let table = rows.table().title(vec![
+        "Provider".cell().bold(true),
+        "Enabled".cell().bold(true),
+        "Description".cell().bold(true),
+        "IAM Actions".cell().bold(true),
+    ]);
+    print_stdout(table)?;
+    Ok(())
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Confirmation {
+    Skip,
+    Region,
+    EachGroup,
+}
+
+async fn clean_unused(
+    scanner: &Scanner,
+    allow_incomplete_coverage: bool,
+    confirmation: Confirmation,
+) -> anyhow::Result<()> {
+    let ScanReport { groups, .. } = load_groups(scanner).await?;
+    let plan = Plan::new(&groups, allow_incomplete_coverage);
+    let unused = plan.verify(&groups).accepted;
+    if confirmation == Confirmation::Skip {
+        return delete_groups(scanner, &unused).await;
+    }
+
+    let mut selected = vec![];
+    for ((account_id, region), groups) in unused
+        .iter()
+        .group_by(|x| (&x.account_id, &x.region))
         .into_iter()
     {
-        info!("cleaning {}", region);
-        let sdk_config = aws_config::from_env()
-            .region(aws_sdk_ec2::Region::new(region))
-            .load()
-            .await;
-        let client = aws_sdk_ec2::Client::new(&sdk_config);
-
-        for group in unused_groups {
-            info!("deleting {}", group.group_id);
-            match client
-                .delete_security_group()
-                .set_group_id(Some(group.group_id.clone()))
-                .send()
-                .await
-            {
-                Err(SdkError::ServiceError { err, .. }) => {
-                    warn!("failed to delete {}: {}", group.group_id, err);
-                    Ok(())
+        let groups = groups.copied().collect_vec();
+        selected.extend(confirm_region(account_id, region, &groups, confirmation)?);
+    }
+    delete_groups(scanner, &selected).await
+}
+
+/// Shows the unused groups of one region and asks which of them to delete. End of input
+/// or any unexpected answer deletes nothing.
+fn confirm_region<'a>(
+    account_id: &str,
+    region: &str,
+    groups: &[&'a ExistingGroup],
+    confirmation: Confirmation,
+) -> anyhow::Result<Vec<&'a ExistingGroup>> {
+    let table = groups
+        .iter()
+        .map(|group| {
+            vec![
+                group.group_id.as_str().cell(),
+                group.group_name.as_str().cell(),
+                group.vpc_id.as_deref().unwrap_or("").cell(),
+                group.group_description.as_str().cell(),
+            ]
+        })
+        .table()
+        .title(vec![
+            "Group ID".cell().bold(true),
+            "Name".cell().bold(true),
+            "VPC".cell().bold(true),
+            "Description".cell().bold(true),
+        ]);
+    println!();
+    println!(
+        "{} unused groups in {} of {}",
+        groups.len(),
+        region,
+        account_id
+    );
+    print_stdout(table)?;
+
+    let question = format!("Delete {} groups in {}?", groups.len(), region);
+    if confirmation == Confirmation::Region {
+        return Ok(match prompt(&format!("{} [y/N]", question))?.as_str() {
+            "y" | "yes" => groups.to_vec(),
+            _ => vec![],
+        });
+    }
+    match prompt(&format!("{} [a]ll, [n]one, [e]ach", question))?.as_str() {
+        "a" | "all" => Ok(groups.to_vec()),
+        "e" | "each" => {
+            let mut selected = vec![];
+            for group in groups {
+                let answer = prompt(&format!(
+                    "Delete {} ({})? [y/N]",
+                    group.group_id, group.group_name
+                ))?;
+                if matches!(answer.as_str(), "y" | "yes") {
+                    selected.push(*group);
                 }
-                Err(err) => Err(err),
-                _ => Ok(()),
-            }?
+            }
+            Ok(selected)
         }
+        _ => Ok(vec![]),
+    }
+}
+
+/// Prints `question` and reads one lowercase answer from stdin, empty at end of input.
+fn prompt(question: &str) -> anyhow::Result<String> {
+    print!("{} ", question);
+    std::io::stdout().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(answer.trim().to_lowercase())
+}
+
+async fn apply_plan(scanner: &Scanner, path: &Path) -> anyhow::Result<()> {
+    let plan = Plan::load(path)?;
+    info!(
+        "loaded {} made at {} with {} groups",
+        path.display(),
+        plan.created_at,
+        plan.groups.len()
+    );
+    let ScanReport { groups, .. } = load_groups(scanner).await?;
+    let verified = plan.verify(&groups);
+    for (group, drift) in verified.refused.iter() {
+        warn!(
+            "refusing {} in {} of {}: {}",
+            group.group_id, group.region, group.account_id, drift
+        );
+    }
+    delete_groups(scanner, &verified.accepted).await?;
+    if !verified.refused.is_empty() {
+        bail!(
+            "refused {} of {} planned groups, plan again to review them",
+            verified.refused.len(),
+            plan.groups.len()
+        );
+    }
+    Ok(())
+}
+
+/// Deletes `groups`, ordered by account and region, one region at a time.
+async fn delete_groups(scanner: &Scanner, groups: &[&ExistingGroup]) -> anyhow::Result<()> {
+    for ((account_id, region), groups) in groups
+        .iter()
+        .group_by(|x| (&x.account_id, &x.region))
+        .into_iter()
+    {
+        info!("cleaning {} of {}", region, account_id);
+        let backend = AwsBackend::new(&scanner.account_client_config(account_id, region).await?)
+            .with_throttle(scanner.throttle().clone());
+
+        let groups = groups.copied().collect_vec();
+        let steps = deletion_order(&groups);
+        cleanup::execute(&backend, &steps).await?;
     }
     Ok(())
 }
@@ -0,0 +1,257 @@
+//! Machine-readable views of [`SecurityGroups`].
+//!
+//! Every format carries [`SCHEMA_VERSION`], which changes whenever a field is removed
+//! or changes meaning. Adding fields does not bump it.
+//!
+//! - `json` and `yaml` write one [`Document`].
+//! - `jsonl` writes one [`GroupRecord`] per line, with a `schema_version` field.
+//! - `csv` writes one row per group with the columns of [`GroupRecord`] and a
+//!   leading `schema_version` column. `tags` is `key=value` pairs and `references`
+//!   is `account/source` entries, both separated by `;`.
+
+use std::collections::{BTreeMap, HashSet};
+use std::io::Write;
+
+use itertools::Itertools;
+use serde::Serialize;
+
+use crate::security::{ExistingGroup, NameReference, SecurityGroups, ServiceReference};
+
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Jsonl,
+    Csv,
+    Yaml,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Document<'a> {
+    pub schema_version: u32,
+    pub groups: Vec<GroupRecord<'a>>,
+    /// Name references that matched no group.
+    pub dangling_references: &'a [NameReference],
+}
+
+#[derive(Debug, Serialize)]
+pub struct GroupRecord<'a> {
+    pub account_id: &'a str,
+    pub region: &'a str,
+    pub group_id: &'a str,
+    pub group_name: &'a str,
+    pub description: &'a str,
+    pub vpc_id: Option<&'a str>,
+    pub owner_id: Option<&'a str>,
+    pub is_default: bool,
+    pub tags: &'a BTreeMap<String, String>,
+    /// Services using the group, directly or through the rules of other groups.
+    pub references: Vec<&'a ServiceReference>,
+    /// Whether every provider succeeded in the region of the group.
+    pub coverage_complete: bool,
+    /// Not referenced, not a default group and not protected. Only safe to delete when
+    /// `coverage_complete` is also set.
+    pub unused: bool,
+    /// Why the configuration protects the group from cleanup.
+    pub protected: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Versioned<T> {
+    schema_version: u32,
+    #[serde(flatten)]
+    record: T,
+}
+
+#[derive(Serialize)]
+struct CsvRow<'a> {
+    schema_version: u32,
+    account_id: &'a str,
+    region: &'a str,
+    group_id: &'a str,
+    group_name: &'a str,
+    description: &'a str,
+    vpc_id: Option<&'a str>,
+    owner_id: Option<&'a str>,
+    is_default: bool,
+    tags: String,
+    references: String,
+    coverage_complete: bool,
+    unused: bool,
+    protected: Option<String>,
+}
+
+/// One record per group, ordered by account, region and group ID.
+pub fn records(groups: &SecurityGroups) -> Vec<GroupRecord<'_>> {
+    let graph = groups.reference_graph();
+    let unused: HashSet<&str> = groups
+        .find_unused()
+        .into_iter()
+        .map(|group| group.group_id.as_str())
+        .collect();
+    groups
+        .existing_groups
+        .iter()
+        .sorted_by_key(|x| (&x.account_id, &x.region, &x.group_id))
+        .map(|group: &ExistingGroup| GroupRecord {
+            account_id: &group.account_id,
+            region: &group.region,
+            group_id: &group.group_id,
+            group_name: &group.group_name,
+            description: &group.group_description,
+            vpc_id: group.vpc_id.as_deref(),
+            owner_id: group.owner_id.as_deref(),
+            is_default: group.is_default,
+            tags: &group.tags,
+            references: graph
+                .referencing_services(&group.group_id)
+                .into_iter()
+                .sorted()
+                .collect(),
+            coverage_complete: groups.is_region_complete(&group.account_id, &group.region),
+            unused: unused.contains(group.group_id.as_str()),
+            protected: groups.protection_reason(group),
+        })
+        .collect()
+}
+
+pub fn write(
+    groups: &SecurityGroups,
+    format: Format,
+    mut writer: impl Write,
+) -> anyhow::Result<()> {
+    let records = records(groups);
+    match format {
+        Format::Json | Format::Yaml => {
+            let document = Document {
+                schema_version: SCHEMA_VERSION,
+                groups: records,
+                dangling_references: &groups.dangling_references,
+            };
+            if format == Format::Json {
+                serde_json::to_writer_pretty(&mut writer, &document)?;
+                writeln!(writer)?;
+            } else {
+                serde_yaml::to_writer(writer, &document)?;
+            }
+        }
+        Format::Jsonl => {
+            for record in records {
+                let record = Versioned {
+                    schema_version: SCHEMA_VERSION,
+                    record,
+                };
+                serde_json::to_writer(&mut writer, &record)?;
+                writeln!(writer)?;
+            }
+        }
+        Format::Csv => {
+            let mut writer = csv::Writer::from_writer(writer);
+            for record in records {
+                writer.serialize(CsvRow {
+                    schema_version: SCHEMA_VERSION,
+                    account_id: record.account_id,
+                    region: record.region,
+                    group_id: record.group_id,
+                    group_name: record.group_name,
+                    description: record.description,
+                    vpc_id: record.vpc_id,
+                    owner_id: record.owner_id,
+                    is_default: record.is_default,
+                    tags: record
+                        .tags
+                        .iter()
+                        .map(|(key, value)| format!("{}={}", key, value))
+                        .join(";"),
+                    references: record.references.iter().join(";"),
+                    coverage_complete: record.coverage_complete,
+                    unused: record.unused,
+                    protected: record.protected,
+                })?;
+            }
+            writer.flush()?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use maplit::hashmap;
+
+    use super::{write, Format};
+    use crate::security::{Coverage, ExistingGroup, SecurityGroups};
+
+    fn groups() -> SecurityGroups {
+        let group = |group_id: &str| ExistingGroup {
+            region: "eu-west-1".to_string(),
+            group_id: group_id.to_string(),
+            group_name: group_id.to_string(),
+            tags: [("team".to_string(), "web".to_string())]
+                .into_iter()
+                .collect(),
+            ..ExistingGroup::default()
+        };
+        let mut groups = SecurityGroups {
+            external_references: hashmap![
+                "sg-1".to_string() => vec!["ec2@eu-west-1".into()],
+            ],
+            existing_groups: vec![group("sg-2"), group("sg-1")],
+            ..SecurityGroups::default()
+        };
+        groups.set_account("1");
+        groups.record_coverage("1", "eu-west-1", "ec2", Coverage::Complete);
+        groups
+    }
+
+    fn output(format: Format) -> String {
+        let mut buf = vec![];
+        write(&groups(), format, &mut buf).unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[test]
+    fn test_json() {
+        let document: serde_json::Value = serde_json::from_str(&output(Format::Json)).unwrap();
+        assert_eq!(document["schema_version"], 1);
+        assert_eq!(document["groups"][0]["group_id"], "sg-1");
+        assert_eq!(document["groups"][0]["unused"], false);
+        assert_eq!(
+            document["groups"][0]["references"][0]["source"],
+            "ec2@eu-west-1"
+        );
+        assert_eq!(document["groups"][1]["unused"], true);
+        assert_eq!(document["groups"][1]["coverage_complete"], true);
+
+        let yaml: serde_json::Value = serde_yaml::from_str(&output(Format::Yaml)).unwrap();
+        assert_eq!(yaml, document);
+    }
+
+    #[test]
+    fn test_jsonl() {
+        let lines = output(Format::Jsonl)
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1]["schema_version"], 1);
+        assert_eq!(lines[1]["group_id"], "sg-2");
+        assert_eq!(lines[1]["tags"]["team"], "web");
+    }
+
+    #[test]
+    fn test_csv() {
+        let csv = output(Format::Csv);
+        let lines = csv.lines().collect::<Vec<_>>();
+        assert_eq!(
+            lines[0],
+            "schema_version,account_id,region,group_id,group_name,description,vpc_id,\
+             owner_id,is_default,tags,references,coverage_complete,unused,protected"
+        );
+        assert_eq!(
+            lines[1],
+            "1,1,eu-west-1,sg-1,sg-1,,,,false,team=web,1/ec2@eu-west-1,true,false,"
+        );
+    }
+}
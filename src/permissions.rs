@@ -0,0 +1,212 @@
+use std::sync::Arc;
+
+use anyhow::Context;
+use itertools::Itertools;
+use serde::Serialize;
+
+use crate::client::ClientConfig;
+use crate::iam;
+use crate::provider::SecurityGroupsProvider;
+
+/// Needed by every scan to list the regions.
+pub const SCAN_ACTIONS: &[&str] = &["ec2:DescribeRegions"];
+
+/// Needed by `--preflight` to simulate the policies of the scanning principal.
+pub const PREFLIGHT_ACTIONS: &[&str] = &["iam:SimulatePrincipalPolicy"];
+
+/// Needed by `--organization` to list the accounts and assume the role in each of them.
+pub const ORGANIZATION_ACTIONS: &[&str] = &["organizations:ListAccounts", "sts:AssumeRole"];
+
+/// Needed by `--role-arn`, by the credentials assuming the role rather than the role.
+pub const ASSUME_ROLE_ACTIONS: &[&str] = &["sts:AssumeRole"];
+
+/// Needed by `clean` on top of the scan.
+pub const CLEAN_ACTIONS: &[&str] = &[
+    "ec2:DescribeSecurityGroups",
+    "ec2:RevokeSecurityGroupIngress",
+    "ec2:RevokeSecurityGroupEgress",
+    "ec2:DeleteSecurityGroup",
+];
+
+/// How the tool runs, deciding the actions it needs on top of the providers.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Usage {
+    pub clean: bool,
+    pub preflight: bool,
+    /// A role is assumed with `--role-arn` before scanning.
+    pub assume_role: bool,
+    pub organization: bool,
+}
+
+/// Actions needed by `providers` and by the tool itself for `usage`, grouped by what
+/// needs them. `assume-role` is needed by the credentials assuming the role, every
+/// other group by the principal that scans.
+pub fn required_actions(
+    providers: &[Arc<dyn SecurityGroupsProvider>],
+    usage: Usage,
+) -> Vec<(&'static str, &'static [&'static str])> {
+    let mut actions = vec![("scan", SCAN_ACTIONS)];
+    if usage.preflight {
+        actions.push(("preflight", PREFLIGHT_ACTIONS));
+    }
+    if usage.organization {
+        actions.push(("organization", ORGANIZATION_ACTIONS));
+    }
+    if usage.assume_role {
+        actions.push(("assume-role", ASSUME_ROLE_ACTIONS));
+    }
+    actions.extend(providers.iter().map(|x| (x.name(), x.iam_actions())));
+    if usage.clean {
+        actions.push(("clean", CLEAN_ACTIONS));
+    }
+    actions
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Policy {
+    pub version: &'static str,
+    pub statement: Vec<Statement>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Statement {
+    pub sid: &'static str,
+    pub effect: &'static str,
+    pub action: Vec<&'static str>,
+    pub resource: &'static str,
+}
+
+impl Statement {
+    fn allow(sid: &'static str, actions: impl IntoIterator<Item = &'static str>) -> Self {
+        Self {
+            sid,
+            effect: "Allow",
+            action: actions.into_iter().sorted().dedup().collect_vec(),
+            resource: "*",
+        }
+    }
+}
+
+/// The minimal identity policy, one statement for scanning and one for cleaning. With
+/// `--role-arn`, a third statement is for the credentials assuming the role.
+pub fn policy(providers: &[Arc<dyn SecurityGroupsProvider>], usage: Usage) -> Policy {
+    let scan = required_actions(
+        providers,
+        Usage {
+            clean: false,
+            assume_role: false,
+            ..usage
+        },
+    )
+    .into_iter()
+    .flat_map(|(_, actions)| actions.iter().copied());
+    let mut statement = vec![Statement::allow("AwsSgCleanupScan", scan)];
+    if usage.assume_role {
+        statement.push(Statement::allow(
+            "AwsSgCleanupAssumeRole",
+            ASSUME_ROLE_ACTIONS.iter().copied(),
+        ));
+    }
+    if usage.clean {
+        statement.push(Statement::allow(
+            "AwsSgCleanupClean",
+            CLEAN_ACTIONS.iter().copied(),
+        ));
+    }
+    Policy {
+        version: "2012-10-17",
+        statement,
+    }
+}
+
+/// Simulates the policies of the principal of `config`, the assumed role with
+/// `--role-arn`, and returns the actions it is missing, grouped like
+/// [`required_actions`]. The roles assumed in member accounts are not simulated.
+pub async fn preflight(
+    config: &ClientConfig,
+    providers: &[Arc<dyn SecurityGroupsProvider>],
+    usage: Usage,
+) -> anyhow::Result<Vec<(&'static str, Vec<String>)>> {
+    let identity = config.sts().get_caller_identity().send().await?;
+    let caller = identity.arn().context("caller identity has no ARN")?;
+    let principal = iam::principal_arn(caller)?;
+
+    let required = required_actions(
+        providers,
+        Usage {
+            assume_role: false,
+            ..usage
+        },
+    );
+    let actions = required
+        .iter()
+        .flat_map(|(_, actions)| actions.iter().copied())
+        .sorted()
+        .dedup()
+        .collect_vec();
+    let denied = iam::denied_actions(config, &principal, &actions).await?;
+
+    Ok(required
+        .into_iter()
+        .filter_map(|(name, actions)| {
+            let missing = actions
+                .iter()
+                .filter(|action| denied.iter().any(|x| x == *action))
+                .map(|action| action.to_string())
+                .collect_vec();
+            (!missing.is_empty()).then_some((name, missing))
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::Usage;
+    use crate::provider::Registry;
+
+    #[test]
+    fn test_policy() {
+        let providers = Registry::builtin()
+            .select(&["ec2-security-groups"], &[])
+            .unwrap();
+        let clean = Usage {
+            clean: true,
+            ..Usage::default()
+        };
+        let policy = super::policy(&providers, clean);
+        assert_eq!(
+            policy.statement[0].action,
+            ["ec2:DescribeRegions", "ec2:DescribeSecurityGroups"]
+        );
+        assert_eq!(policy.statement[1].sid, "AwsSgCleanupClean");
+        assert_eq!(
+            super::policy(&providers, Usage::default()).statement.len(),
+            1
+        );
+
+        let policy = super::policy(
+            &providers,
+            Usage {
+                preflight: true,
+                assume_role: true,
+                organization: true,
+                ..clean
+            },
+        );
+        assert_eq!(
+            policy.statement[0].action,
+            [
+                "ec2:DescribeRegions",
+                "ec2:DescribeSecurityGroups",
+                "iam:SimulatePrincipalPolicy",
+                "organizations:ListAccounts",
+                "sts:AssumeRole"
+            ]
+        );
+        assert_eq!(policy.statement[1].sid, "AwsSgCleanupAssumeRole");
+        assert_eq!(policy.statement[1].action, ["sts:AssumeRole"]);
+        assert_eq!(policy.statement[2].sid, "AwsSgCleanupClean");
+    }
+}
@@ -0,0 +1,385 @@
+use std::{collections::HashMap, fmt, fs::File, io::BufReader, path::Path, time::SystemTime};
+
+use anyhow::{bail, Context};
+use aws_smithy_types::date_time::{DateTime, Format};
+use itertools::Itertools;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+
+use crate::graph::ReferenceGraph;
+use crate::security::{ExistingGroup, Rule, SecurityGroups, ServiceReference};
+
+const VERSION: u32 = 1;
+
+/// Groups to delete, reviewed before [`Plan::verify`] checks them against a fresh scan.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Plan {
+    pub version: u32,
+    /// RFC 3339 time the plan was made.
+    pub created_at: String,
+    /// Whether regions where some providers failed were planned, and may be applied.
+    pub allow_incomplete_coverage: bool,
+    /// Ordered by account, region and group ID.
+    pub groups: Vec<PlannedGroup>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlannedGroup {
+    pub account_id: String,
+    pub region: String,
+    pub group_id: String,
+    pub group_name: String,
+    /// See [`checksum`].
+    pub checksum: String,
+}
+
+/// Why a planned group is not deleted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Drift {
+    Missing,
+    /// Default groups cannot be deleted, so only a hand-edited plan lists one.
+    Default,
+    Used,
+    Protected,
+    Changed,
+    IncompleteCoverage,
+}
+
+impl fmt::Display for Drift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Missing => "no longer exists",
+            Self::Default => "is a default group",
+            Self::Used => "is used now",
+            Self::Protected => "is protected now",
+            Self::Changed => "rules or references changed",
+            Self::IncompleteCoverage => "coverage of its region is incomplete",
+        })
+    }
+}
+
+/// The planned groups that still match their checksums, and the ones refused.
+#[derive(Debug, Default)]
+pub struct Verified<'a, 'p> {
+    /// In plan order.
+    pub accepted: Vec<&'a ExistingGroup>,
+    pub refused: Vec<(&'p PlannedGroup, Drift)>,
+}
+
+impl Plan {
+    /// Plans every unused group. Regions with incomplete coverage are skipped unless
+    /// `allow_incomplete_coverage` is set.
+    pub fn new(groups: &SecurityGroups, allow_incomplete_coverage: bool) -> Self {
+        let unused = groups
+            .find_unused()
+            .into_iter()
+            .sorted_by_key(|x| (&x.account_id, &x.region, &x.group_id))
+            .group_by(|x| (&x.account_id, &x.region))
+            .into_iter()
+            .filter(|((account_id, region), _)| {
+                if groups.is_region_complete(account_id, region) {
+                    return true;
+                }
+                let gaps = groups
+                    .coverage_gaps(account_id, region)
+                    .into_iter()
+                    .map(|(provider, _)| provider)
+                    .join(", ");
+                if allow_incomplete_coverage {
+                    warn!(
+                        "planning {} of {} despite incomplete coverage ({})",
+                        region, account_id, gaps
+                    );
+                } else {
+                    warn!(
+                        "skipping {} of {}: coverage incomplete ({})",
+                        region, account_id, gaps
+                    );
+                }
+                allow_incomplete_coverage
+            })
+            .flat_map(|(_, unused)| unused)
+            .collect_vec();
+        Self::from_groups(groups, unused, allow_incomplete_coverage)
+    }
+
+    /// Plans `selected`, a hand-picked subset of `groups`.
+    pub fn from_groups<'a>(
+        groups: &SecurityGroups,
+        selected: impl IntoIterator<Item = &'a ExistingGroup>,
+        allow_incomplete_coverage: bool,
+    ) -> Self {
+        let index = ChecksumIndex::new(groups);
+        let planned = selected
+            .into_iter()
+            .sorted_by_key(|x| (&x.account_id, &x.region, &x.group_id))
+            .map(|group| PlannedGroup {
+                account_id: group.account_id.clone(),
+                region: group.region.clone(),
+                group_id: group.group_id.clone(),
+                group_name: group.group_name.clone(),
+                checksum: checksum(&index, group),
+            })
+            .collect_vec();
+        Self {
+            version: VERSION,
+            created_at: DateTime::from(SystemTime::now())
+                .fmt(Format::DateTime)
+                .unwrap_or_default(),
+            allow_incomplete_coverage,
+            groups: planned,
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let file =
+            File::create(path).with_context(|| format!("failed to create {}", path.display()))?;
+        serde_json::to_writer_pretty(file, self)
+            .with_context(|| format!("failed to write {}", path.display()))?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let file =
+            File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+        let plan: Self = serde_json::from_reader(BufReader::new(file))
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        if plan.version != VERSION {
+            bail!(
+                "{} has plan version {}, expected {}",
+                path.display(),
+                plan.version,
+                VERSION
+            );
+        }
+        Ok(plan)
+    }
+
+    /// Matches the planned groups against `live`, refusing every group that drifted.
+    pub fn verify<'a, 'p>(&'p self, live: &'a SecurityGroups) -> Verified<'a, 'p> {
+        let index = ChecksumIndex::new(live);
+        let existing: HashMap<(&str, &str, &str), &ExistingGroup> = live
+            .existing_groups
+            .iter()
+            .map(|x| {
+                (
+                    (
+                        x.account_id.as_str(),
+                        x.region.as_str(),
+                        x.group_id.as_str(),
+                    ),
+                    x,
+                )
+            })
+            .collect();
+        let mut verified = Verified::default();
+        for planned in self.groups.iter() {
+            let key = (
+                planned.account_id.as_str(),
+                planned.region.as_str(),
+                planned.group_id.as_str(),
+            );
+            let Some(group) = existing.get(&key).copied() else {
+                verified.refused.push((planned, Drift::Missing));
+                continue;
+            };
+            if group.is_default {
+                verified.refused.push((planned, Drift::Default));
+            } else if index.graph.is_used(&group.group_id) {
+                verified.refused.push((planned, Drift::Used));
+            } else if live.protection_reason(group).is_some() {
+                verified.refused.push((planned, Drift::Protected));
+            } else if checksum(&index, group) != planned.checksum {
+                verified.refused.push((planned, Drift::Changed));
+            } else if !self.allow_incomplete_coverage
+                && !live.is_region_complete(&group.account_id, &group.region)
+            {
+                verified.refused.push((planned, Drift::IncompleteCoverage));
+            } else {
+                verified.accepted.push(group);
+            }
+        }
+        verified
+    }
+}
+
+#[derive(Serialize)]
+struct GroupState<'a> {
+    ingress: Vec<String>,
+    egress: Vec<String>,
+    references: Vec<&'a str>,
+    referenced_by: Vec<&'a str>,
+    services: Vec<&'a ServiceReference>,
+}
+
+/// The reference graph and reverse references of an inventory, built once for every
+/// [`checksum`] of it.
+pub struct ChecksumIndex<'a> {
+    graph: ReferenceGraph<'a>,
+    referenced_by: HashMap<&'a str, Vec<&'a str>>,
+}
+
+impl<'a> ChecksumIndex<'a> {
+    pub fn new(groups: &'a SecurityGroups) -> Self {
+        let mut referenced_by: HashMap<&str, Vec<&str>> = HashMap::new();
+        for group in groups.existing_groups.iter() {
+            for target in group.references.iter() {
+                referenced_by
+                    .entry(target)
+                    .or_default()
+                    .push(&group.group_id);
+            }
+        }
+        Self {
+            graph: groups.reference_graph(),
+            referenced_by,
+        }
+    }
+}
+
+/// SHA-1 of the rules of `group`, the groups it references, the groups referencing it
+/// and the services using it, independent of the order AWS returns them in.
+pub fn checksum(index: &ChecksumIndex<'_>, group: &ExistingGroup) -> String {
+    let rules = |rules: &[Rule]| {
+        rules
+            .iter()
+            .map(|rule| serde_json::to_string(rule).unwrap_or_default())
+            .sorted()
+            .collect_vec()
+    };
+    let state = GroupState {
+        ingress: rules(&group.ingress),
+        egress: rules(&group.egress),
+        references: group
+            .references
+            .iter()
+            .map(String::as_str)
+            .sorted()
+            .collect(),
+        referenced_by: index
+            .referenced_by
+            .get(group.group_id.as_str())
+            .into_iter()
+            .flatten()
+            .copied()
+            .sorted()
+            .collect(),
+        services: index
+            .graph
+            .referencing_services(&group.group_id)
+            .into_iter()
+            .sorted()
+            .collect(),
+    };
+    let mut hasher = Sha1::new();
+    hasher.update(serde_json::to_vec(&state).unwrap_or_default());
+    base16ct::lower::encode_string(&hasher.finalize())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Drift, Plan};
+    use crate::security::{Coverage, ExistingGroup, Rule, RulePeer, SecurityGroups};
+
+    fn groups() -> SecurityGroups {
+        let group = |group_id: &str, references: &[&str]| ExistingGroup {
+            account_id: "1".to_string(),
+            region: "eu-west-1".to_string(),
+            group_id: group_id.to_string(),
+            group_name: group_id.to_string(),
+            ingress: references
+                .iter()
+                .map(|peer| Rule {
+                    protocol: "tcp".to_string(),
+                    from_port: Some(443),
+                    to_port: Some(443),
+                    peer: RulePeer::Group {
+                        group_id: peer.to_string(),
+                        user_id: None,
+                    },
+                    description: None,
+                })
+                .collect(),
+            references: references.iter().map(|x| x.to_string()).collect(),
+            ..ExistingGroup::default()
+        };
+        let mut groups = SecurityGroups {
+            existing_groups: vec![
+                group("sg-1", &[]),
+                group("sg-2", &["sg-1"]),
+                group("sg-3", &[]),
+                group("sg-4", &[]),
+            ],
+            ..SecurityGroups::default()
+        };
+        groups.record_coverage("1", "eu-west-1", "ec2", Coverage::Complete);
+        groups
+    }
+
+    #[test]
+    fn test_verify() {
+        let plan = Plan::new(&groups(), false);
+        assert_eq!(plan.groups.len(), 4);
+        let plan: Plan = serde_json::from_str(&serde_json::to_string(&plan).unwrap()).unwrap();
+
+        let live = groups();
+        let verified = plan.verify(&live);
+        assert_eq!(verified.accepted.len(), 4);
+        assert!(verified.refused.is_empty());
+
+        let mut live = groups();
+        // sg-1 loses the reference from sg-2, sg-3 becomes used and sg-4 is gone.
+        live.existing_groups[1].ingress.clear();
+        live.existing_groups[1].references.clear();
+        live.external_references
+            .insert("sg-3".to_string(), vec!["ec2@eu-west-1".into()]);
+        live.existing_groups.pop();
+        let verified = plan.verify(&live);
+        let refused = verified
+            .refused
+            .iter()
+            .map(|(group, drift)| (group.group_id.as_str(), *drift))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            refused,
+            [
+                ("sg-1", Drift::Changed),
+                ("sg-2", Drift::Changed),
+                ("sg-3", Drift::Used),
+                ("sg-4", Drift::Missing),
+            ]
+        );
+        assert!(verified.accepted.is_empty());
+
+        // A hand-edited plan listing a default group never deletes it.
+        let mut live = groups();
+        live.existing_groups[2].is_default = true;
+        let plan = Plan::from_groups(&live, &live.existing_groups[2..3], false);
+        let verified = plan.verify(&live);
+        assert_eq!(verified.refused[0].1, Drift::Default);
+        assert!(verified.accepted.is_empty());
+    }
+
+    #[test]
+    fn test_incomplete_coverage() {
+        let mut groups = groups();
+        groups.record_coverage(
+            "1",
+            "eu-west-1",
+            "rds",
+            Coverage::Failed("denied".to_string()),
+        );
+        assert!(Plan::new(&groups, false).groups.is_empty());
+
+        let plan = Plan::new(&groups, true);
+        assert_eq!(plan.verify(&groups).accepted.len(), 4);
+
+        let plan = Plan::new(&self::groups(), false);
+        let verified = plan.verify(&groups);
+        assert!(verified
+            .refused
+            .iter()
+            .all(|(_, drift)| *drift == Drift::IncompleteCoverage));
+    }
+}
@@ -0,0 +1,123 @@
+use std::collections::{BTreeMap, HashSet};
+
+use anyhow::Context;
+use regex::Regex;
+
+use crate::security::ExistingGroup;
+
+/// Groups that are never cleanup candidates, whatever references them.
+#[derive(Clone, Debug, Default)]
+pub struct Protection {
+    group_ids: HashSet<String>,
+    names: Vec<Regex>,
+    descriptions: Vec<Regex>,
+    tag_keys: HashSet<String>,
+    tags: BTreeMap<String, String>,
+}
+
+impl Protection {
+    /// Compiles the name and description patterns, which match anywhere in the text
+    /// unless anchored.
+    pub fn new(
+        group_ids: impl IntoIterator<Item = String>,
+        names: &[String],
+        descriptions: &[String],
+        tag_keys: impl IntoIterator<Item = String>,
+        tags: BTreeMap<String, String>,
+    ) -> anyhow::Result<Self> {
+        let compile = |patterns: &[String]| {
+            patterns
+                .iter()
+                .map(|pattern| {
+                    Regex::new(pattern).with_context(|| format!("invalid pattern {}", pattern))
+                })
+                .collect::<anyhow::Result<Vec<_>>>()
+        };
+        Ok(Self {
+            group_ids: group_ids.into_iter().collect(),
+            names: compile(names)?,
+            descriptions: compile(descriptions)?,
+            tag_keys: tag_keys.into_iter().collect(),
+            tags,
+        })
+    }
+
+    /// Why `group` is protected, or `None` when it is not.
+    pub fn reason(&self, group: &ExistingGroup) -> Option<String> {
+        if self.group_ids.contains(&group.group_id) {
+            return Some("protected group ID".to_string());
+        }
+        if let Some(pattern) = self.names.iter().find(|x| x.is_match(&group.group_name)) {
+            return Some(format!("name matches {}", pattern));
+        }
+        if let Some(pattern) = self
+            .descriptions
+            .iter()
+            .find(|x| x.is_match(&group.group_description))
+        {
+            return Some(format!("description matches {}", pattern));
+        }
+        if let Some(key) = group.tags.keys().find(|key| self.tag_keys.contains(*key)) {
+            return Some(format!("tag {}", key));
+        }
+        self.tags
+            .iter()
+            .find(|(key, value)| group.tags.get(*key) == Some(value))
+            .map(|(key, value)| format!("tag {}={}", key, value))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Protection;
+    use crate::security::ExistingGroup;
+
+    #[test]
+    fn test_reason() {
+        let protection = Protection::new(
+            ["sg-1".to_string()],
+            &["^baseline-".to_string()],
+            &["(?i)do not delete".to_string()],
+            ["Protected".to_string()],
+            [("env".to_string(), "prod".to_string())]
+                .into_iter()
+                .collect(),
+        )
+        .unwrap();
+        let group =
+            |group_id: &str, name: &str, description: &str, tag: (&str, &str)| ExistingGroup {
+                group_id: group_id.to_string(),
+                group_name: name.to_string(),
+                group_description: description.to_string(),
+                tags: [(tag.0.to_string(), tag.1.to_string())]
+                    .into_iter()
+                    .collect(),
+                ..ExistingGroup::default()
+            };
+        let reason = |group: ExistingGroup| protection.reason(&group);
+
+        assert_eq!(
+            reason(group("sg-1", "web", "", ("a", "b"))).as_deref(),
+            Some("protected group ID")
+        );
+        assert_eq!(
+            reason(group("sg-2", "baseline-vpn", "", ("a", "b"))).as_deref(),
+            Some("name matches ^baseline-")
+        );
+        assert_eq!(
+            reason(group("sg-2", "web-baseline-", "DO NOT DELETE", ("a", "b"))).as_deref(),
+            Some("description matches (?i)do not delete")
+        );
+        assert_eq!(
+            reason(group("sg-2", "web", "", ("Protected", ""))).as_deref(),
+            Some("tag Protected")
+        );
+        assert_eq!(
+            reason(group("sg-2", "web", "", ("env", "prod"))).as_deref(),
+            Some("tag env=prod")
+        );
+        assert_eq!(reason(group("sg-2", "web", "", ("env", "dev"))), None);
+
+        assert!(Protection::new([], &["(".to_string()], &[], [], Default::default()).is_err());
+    }
+}
@@ -0,0 +1,131 @@
+use std::sync::Arc;
+
+use anyhow::bail;
+use async_trait::async_trait;
+use itertools::Itertools;
+
+use crate::backend::Backend;
+use crate::error::ProviderError;
+use crate::security::SecurityGroups;
+
+/// A source of security group references, such as the instances or the load
+/// balancers of a region.
+#[async_trait]
+pub trait SecurityGroupsProvider: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn description(&self) -> &'static str;
+    /// IAM actions the provider needs to load every reference.
+    fn iam_actions(&self) -> &'static [&'static str];
+
+    async fn load(&self, backend: &dyn Backend) -> Result<SecurityGroups, ProviderError>;
+}
+
+/// Names of every built-in provider, whether compiled in or not. A scan records the
+/// ones it does not run as skipped, leaving their regions incomplete.
+pub const BUILTIN_PROVIDERS: &[&str] = &[
+    "ec2",
+    "alb",
+    "elasticache",
+    "lambda",
+    "rds",
+    "ec2-security-groups",
+];
+
+/// Providers available to a scan, by name.
+#[derive(Clone, Default)]
+pub struct Registry {
+    providers: Vec<Arc<dyn SecurityGroupsProvider>>,
+}
+
+impl Registry {
+    /// Every provider compiled into this build.
+    pub fn builtin() -> Self {
+        let mut registry = Self::default();
+        #[cfg(feature = "ec2")]
+        registry.register(crate::ec2::EC2Groups {});
+        #[cfg(feature = "alb")]
+        registry.register(crate::alb::ALBGroups {});
+        #[cfg(feature = "elasticache")]
+        registry.register(crate::elasticache::ElasticacheGroups {});
+        #[cfg(feature = "lambda")]
+        registry.register(crate::lambda::LambdaGroups {});
+        #[cfg(feature = "rds")]
+        registry.register(crate::rds::RDSGroups {});
+        registry.register(crate::security::AWSSecurityGroups {});
+        registry
+    }
+
+    /// Adds `provider`, replacing any provider registered under the same name.
+    pub fn register(&mut self, provider: impl SecurityGroupsProvider + 'static) {
+        self.providers.retain(|x| x.name() != provider.name());
+        self.providers.push(Arc::new(provider));
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Arc<dyn SecurityGroupsProvider>> {
+        self.providers.iter().find(|x| x.name() == name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Arc<dyn SecurityGroupsProvider>> {
+        self.providers.iter()
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        self.providers.iter().map(|x| x.name()).collect_vec()
+    }
+
+    /// The providers named in `include`, all of them when it is empty, minus the ones
+    /// named in `skip`. Unknown names are an error.
+    pub fn select<S: AsRef<str>>(
+        &self,
+        include: &[S],
+        skip: &[S],
+    ) -> anyhow::Result<Vec<Arc<dyn SecurityGroupsProvider>>> {
+        for name in include.iter().chain(skip.iter()) {
+            if self.get(name.as_ref()).is_none() {
+                bail!(
+                    "unknown provider {}, expected one of {}",
+                    name.as_ref(),
+                    self.names().join(", ")
+                );
+            }
+        }
+        Ok(self
+            .providers
+            .iter()
+            .filter(|x| include.is_empty() || include.iter().any(|name| name.as_ref() == x.name()))
+            .filter(|x| !skip.iter().any(|name| name.as_ref() == x.name()))
+            .cloned()
+            .collect_vec())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use super::{Registry, SecurityGroupsProvider};
+
+    #[test]
+    fn test_select() {
+        let registry = Registry::builtin();
+        let names = |providers: Vec<Arc<dyn SecurityGroupsProvider>>| {
+            providers.iter().map(|x| x.name()).collect::<Vec<_>>()
+        };
+        let none: &[&str] = &[];
+
+        assert_eq!(
+            names(registry.select(none, none).unwrap()),
+            registry.names()
+        );
+        assert_eq!(
+            names(registry.select(&["ec2-security-groups"], none).unwrap()),
+            ["ec2-security-groups"]
+        );
+        assert!(
+            !names(registry.select(none, &["ec2-security-groups"]).unwrap())
+                .contains(&"ec2-security-groups")
+        );
+        assert!(registry.select(&["s3"], none).is_err());
+        assert!(registry.select(none, &["s3"]).is_err());
+    }
+}
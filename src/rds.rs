@@ -1,62 +1,76 @@
 use async_trait::async_trait;
-use aws_sdk_rds::Client;
-use aws_types::SdkConfig;
-use futures::StreamExt;
 use itertools::Itertools;
 
-use crate::security::{SecurityGroups, SecurityGroupsProvider};
+use crate::backend::Backend;
+use crate::error::ProviderError;
+use crate::provider::SecurityGroupsProvider;
+use crate::security::{NameReference, SecurityGroups};
 
 pub struct RDSGroups {}
 
 #[async_trait]
-impl SecurityGroupsProvider<SdkConfig> for RDSGroups {
-    async fn load(config: &SdkConfig) -> SecurityGroups {
-        let client = Client::new(config);
-        let db = client
-            .describe_db_instances()
-            .into_paginator()
-            .items()
-            .send()
-            .flat_map(|item| {
-                let db = item.unwrap();
-                let vpc = db
-                    .vpc_security_groups()
-                    .unwrap_or_default()
-                    .iter()
-                    .map(|group| group.vpc_security_group_id().unwrap().to_owned())
-                    .collect_vec();
-                let classic = db
-                    .db_security_groups()
-                    .unwrap_or_default()
-                    .iter()
-                    .map(|group| group.db_security_group_name().unwrap().to_owned())
-                    .collect_vec();
-                futures::stream::iter(itertools::chain(vpc, classic))
+impl SecurityGroupsProvider for RDSGroups {
+    fn name(&self) -> &'static str {
+        "rds"
+    }
+
+    fn description(&self) -> &'static str {
+        "RDS instances and Aurora clusters, including DB security groups"
+    }
+
+    fn iam_actions(&self) -> &'static [&'static str] {
+        &["rds:DescribeDBInstances", "rds:DescribeDBClusters"]
+    }
+
+    async fn load(&self, backend: &dyn Backend) -> Result<SecurityGroups, ProviderError> {
+        let region = backend.region();
+        let (instances, clusters) = tokio::join!(
+            backend.describe_db_instances(),
+            backend.describe_db_clusters()
+        );
+        let instances = instances.map_err(|err| ProviderError::new(self.name(), &region, err))?;
+        let clusters = clusters.map_err(|err| ProviderError::new(self.name(), &region, err))?;
+
+        let db = instances.iter().map(|db| {
+            (
+                format!("DB instance {}", db.db_instance_identifier),
+                db.security_groups.clone(),
+            )
+        });
+        let aurora = clusters.iter().map(|cluster| {
+            (
+                format!("DB cluster {}", cluster.db_cluster_identifier),
+                cluster.security_groups.clone(),
+            )
+        });
+
+        let source = format!("{}@{}", self.name(), region);
+        let name_references = instances
+            .iter()
+            .flat_map(|db| {
+                db.db_security_groups.iter().map(move |group_name| {
+                    (
+                        (db.vpc_id.clone(), group_name),
+                        format!("DB instance {}", db.db_instance_identifier),
+                    )
+                })
             })
-            .collect::<Vec<_>>();
-        let aurora = client
-            .describe_db_clusters()
-            .into_paginator()
-            .items()
-            .send()
-            .flat_map(|item| {
-                let cluster = item.unwrap();
-                futures::stream::iter(
-                    cluster
-                        .vpc_security_groups()
-                        .unwrap_or_default()
-                        .iter()
-                        .map(|group| group.vpc_security_group_id().unwrap().to_owned())
-                        .collect_vec(),
-                )
+            .into_group_map()
+            .into_iter()
+            .sorted()
+            .map(|((vpc_id, group_name), resources)| NameReference {
+                account_id: String::new(),
+                region: region.clone(),
+                vpc_id,
+                group_name: group_name.clone(),
+                source: source.clone(),
+                resources,
             })
-            .collect::<Vec<_>>();
-
-        let (db, aurora) = tokio::join!(db, aurora);
+            .collect_vec();
 
-        SecurityGroups::create_from_group_ids(
-            format!("rds@{}", config.region().unwrap()),
-            itertools::chain(db, aurora),
-        )
+        Ok(SecurityGroups {
+            name_references,
+            ..SecurityGroups::create_from_resources(source, itertools::chain(db, aurora))
+        })
     }
 }
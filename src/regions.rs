@@ -0,0 +1,152 @@
+use aws_config::RetryConfig;
+use itertools::Itertools;
+use log::info;
+
+use crate::client::{ClientConfig, ClientOptions};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OptInStatus {
+    /// Enabled for every account.
+    NotRequired,
+    OptedIn,
+    /// An opt-in region the account has not enabled. Calls fail with auth errors.
+    NotOptedIn,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RegionInfo {
+    pub name: String,
+    pub opt_in_status: OptInStatus,
+}
+
+impl RegionInfo {
+    pub fn is_enabled(&self) -> bool {
+        self.opt_in_status != OptInStatus::NotOptedIn
+    }
+}
+
+/// Every region of the partition, including the ones not enabled for the account.
+pub async fn load_regions(options: &ClientOptions) -> anyhow::Result<Vec<RegionInfo>> {
+    info!("loading regions");
+    let config = ClientConfig::load(options.default_region(), options, RetryConfig::new()).await;
+    let response = config
+        .ec2()
+        .describe_regions()
+        .all_regions(true)
+        .send()
+        .await?;
+
+    Ok(response
+        .regions()
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|region| {
+            let opt_in_status = match region.opt_in_status() {
+                Some("opted-in") => OptInStatus::OptedIn,
+                Some("not-opted-in") => OptInStatus::NotOptedIn,
+                _ => OptInStatus::NotRequired,
+            };
+            Some(RegionInfo {
+                name: region.region_name()?.to_owned(),
+                opt_in_status,
+            })
+        })
+        .collect_vec())
+}
+
+/// Which regions to scan: the ones matching any `include` glob, all when empty, and
+/// no `exclude` glob. Globs support `*` and `?`.
+#[derive(Clone, Debug, Default)]
+pub struct RegionFilter {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    /// Also leave out opt-in regions the account has enabled.
+    pub skip_opt_in: bool,
+}
+
+impl RegionFilter {
+    pub fn matches(&self, region: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|x| glob_match(x, region)))
+            && !self.exclude.iter().any(|x| glob_match(x, region))
+    }
+
+    /// Splits `regions` into the ones to scan and the matching ones that are not
+    /// enabled for the account.
+    pub fn select(&self, regions: Vec<RegionInfo>) -> (Vec<RegionInfo>, Vec<RegionInfo>) {
+        regions
+            .into_iter()
+            .filter(|region| self.matches(&region.name))
+            .filter(|region| !(self.skip_opt_in && region.opt_in_status == OptInStatus::OptedIn))
+            .partition(RegionInfo::is_enabled)
+    }
+}
+
+fn glob_match(pattern: &str, text: &str) -> bool {
+    match pattern.chars().next() {
+        None => text.is_empty(),
+        Some('*') => {
+            let rest = &pattern[1..];
+            text.char_indices()
+                .map(|(i, _)| i)
+                .chain([text.len()])
+                .any(|i| glob_match(rest, &text[i..]))
+        }
+        Some(c) => {
+            let mut chars = text.chars();
+            match chars.next() {
+                Some(t) if c == '?' || c == t => {
+                    glob_match(&pattern[c.len_utf8()..], chars.as_str())
+                }
+                _ => false,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{glob_match, OptInStatus, RegionFilter, RegionInfo};
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("eu-*", "eu-west-1"));
+        assert!(glob_match("*-west-?", "us-west-2"));
+        assert!(glob_match("us-east-1", "us-east-1"));
+        assert!(!glob_match("us-east-1", "us-east-11"));
+        assert!(!glob_match("eu-*", "us-east-1"));
+        assert!(glob_match("*", ""));
+    }
+
+    #[test]
+    fn test_select() {
+        let region = |name: &str, opt_in_status| RegionInfo {
+            name: name.to_string(),
+            opt_in_status,
+        };
+        let regions = vec![
+            region("eu-west-1", OptInStatus::NotRequired),
+            region("eu-south-1", OptInStatus::OptedIn),
+            region("eu-central-2", OptInStatus::NotOptedIn),
+            region("us-east-1", OptInStatus::NotRequired),
+        ];
+        let names =
+            |regions: Vec<RegionInfo>| regions.into_iter().map(|x| x.name).collect::<Vec<_>>();
+
+        let filter = RegionFilter {
+            include: vec!["eu-*".to_string()],
+            ..RegionFilter::default()
+        };
+        let (enabled, disabled) = filter.select(regions.clone());
+        assert_eq!(names(enabled), ["eu-west-1", "eu-south-1"]);
+        assert_eq!(names(disabled), ["eu-central-2"]);
+
+        let filter = RegionFilter {
+            exclude: vec!["eu-central-*".to_string()],
+            skip_opt_in: true,
+            ..RegionFilter::default()
+        };
+        let (enabled, disabled) = filter.select(regions);
+        assert_eq!(names(enabled), ["eu-west-1", "us-east-1"]);
+        assert!(disabled.is_empty());
+    }
+}
@@ -0,0 +1,429 @@
+//! Self-contained HTML and Markdown reports for reviewing an inventory.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use itertools::Itertools;
+
+use crate::output::{records, GroupRecord};
+use crate::security::{Coverage, SecurityGroups};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Html,
+    Markdown,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Summary {
+    pub groups: usize,
+    /// Unused groups in regions with complete coverage, the ones a cleanup deletes.
+    pub unused: usize,
+    /// Unused groups in regions with incomplete coverage, which may still be used by a
+    /// service that was not scanned.
+    pub unverified: usize,
+    pub default: usize,
+}
+
+impl Summary {
+    fn add(&mut self, record: &GroupRecord) {
+        self.groups += 1;
+        self.unused += (record.unused && record.coverage_complete) as usize;
+        self.unverified += (record.unused && !record.coverage_complete) as usize;
+        self.default += record.is_default as usize;
+    }
+}
+
+/// A failed provider in one region of one account.
+#[derive(Debug, PartialEq, Eq)]
+pub struct CoverageGap<'a> {
+    pub account_id: &'a str,
+    pub region: &'a str,
+    pub provider: &'a str,
+    pub reason: &'a str,
+}
+
+/// Group counts by account and region.
+pub fn region_summaries<'a>(records: &[GroupRecord<'a>]) -> BTreeMap<(&'a str, &'a str), Summary> {
+    let mut summaries: BTreeMap<_, Summary> = BTreeMap::new();
+    for record in records {
+        summaries
+            .entry((record.account_id, record.region))
+            .or_default()
+            .add(record);
+    }
+    summaries
+}
+
+/// Group counts by account, region and VPC, with `-` for groups outside any VPC.
+pub fn vpc_summaries<'a>(
+    records: &[GroupRecord<'a>],
+) -> BTreeMap<(&'a str, &'a str, &'a str), Summary> {
+    let mut summaries: BTreeMap<_, Summary> = BTreeMap::new();
+    for record in records {
+        summaries
+            .entry((
+                record.account_id,
+                record.region,
+                record.vpc_id.unwrap_or("-"),
+            ))
+            .or_default()
+            .add(record);
+    }
+    summaries
+}
+
+pub fn coverage_gaps(groups: &SecurityGroups) -> Vec<CoverageGap<'_>> {
+    groups
+        .coverage
+        .iter()
+        .flat_map(|(account_id, regions)| {
+            regions.iter().flat_map(move |(region, providers)| {
+                providers
+                    .iter()
+                    .filter_map(move |(provider, coverage)| match coverage {
+                        Coverage::Complete => None,
+                        Coverage::Failed(reason) => Some(CoverageGap {
+                            account_id,
+                            region,
+                            provider,
+                            reason,
+                        }),
+                        Coverage::Skipped => Some(CoverageGap {
+                            account_id,
+                            region,
+                            provider,
+                            reason: "skipped",
+                        }),
+                    })
+            })
+        })
+        .sorted_by_key(|x| (x.account_id, x.region, x.provider))
+        .collect()
+}
+
+/// Renders the report. `title` usually carries the scan time.
+pub fn render(groups: &SecurityGroups, format: Format, title: &str) -> String {
+    let records = records(groups);
+    let total = records.iter().fold(Summary::default(), |mut acc, record| {
+        acc.add(record);
+        acc
+    });
+    let sections = Sections {
+        total,
+        regions: region_summaries(&records)
+            .into_iter()
+            .map(|((account, region), summary)| (vec![account, region], summary))
+            .collect(),
+        vpcs: vpc_summaries(&records)
+            .into_iter()
+            .map(|((account, region, vpc), summary)| (vec![account, region, vpc], summary))
+            .collect(),
+        gaps: coverage_gaps(groups),
+        groups: records,
+    };
+    match format {
+        Format::Html => html(title, &sections),
+        Format::Markdown => markdown(title, &sections),
+    }
+}
+
+struct Sections<'a> {
+    total: Summary,
+    regions: Vec<(Vec<&'a str>, Summary)>,
+    vpcs: Vec<(Vec<&'a str>, Summary)>,
+    gaps: Vec<CoverageGap<'a>>,
+    groups: Vec<GroupRecord<'a>>,
+}
+
+const SUMMARY_COLUMNS: [&str; 4] = ["Groups", "Unused", "Unverified", "Default"];
+const GROUP_COLUMNS: [&str; 9] = [
+    "Account",
+    "Region",
+    "VPC",
+    "Group ID",
+    "Group Name",
+    "Unused",
+    "Coverage",
+    "References",
+    "Tags",
+];
+
+fn summary_cells(summary: &Summary) -> Vec<String> {
+    vec![
+        summary.groups.to_string(),
+        summary.unused.to_string(),
+        summary.unverified.to_string(),
+        summary.default.to_string(),
+    ]
+}
+
+fn group_cells(record: &GroupRecord) -> Vec<String> {
+    vec![
+        record.account_id.to_owned(),
+        record.region.to_owned(),
+        record.vpc_id.unwrap_or_default().to_owned(),
+        record.group_id.to_owned(),
+        record.group_name.to_owned(),
+        if record.unused { "yes" } else { "no" }.to_owned(),
+        if record.coverage_complete {
+            "complete"
+        } else {
+            "incomplete"
+        }
+        .to_owned(),
+        record.references.iter().join(", "),
+        record
+            .tags
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .join(", "),
+    ]
+}
+
+fn gap_cells(gap: &CoverageGap) -> Vec<String> {
+    [gap.account_id, gap.region, gap.provider, gap.reason]
+        .map(ToOwned::to_owned)
+        .to_vec()
+}
+
+const GAP_COLUMNS: [&str; 4] = ["Account", "Region", "Provider", "Reason"];
+
+fn markdown(title: &str, sections: &Sections) -> String {
+    fn table(out: &mut String, header: &[&str], rows: impl Iterator<Item = Vec<String>>) {
+        // Renderers allowing inline HTML would otherwise interpret names and tags.
+        let cell = |text: &str| escape(text).replace('|', "\\|").replace('\n', " ");
+        let _ = writeln!(out, "| {} |", header.join(" | "));
+        let _ = writeln!(out, "|{}", "---|".repeat(header.len()));
+        for row in rows {
+            let _ = writeln!(out, "| {} |", row.iter().map(|x| cell(x)).join(" | "));
+        }
+        out.push('\n');
+    }
+
+    let mut out = String::new();
+    let _ = writeln!(out, "# {}\n", escape(title));
+    let _ = writeln!(
+        out,
+        "{} groups, {} unused, {} unverified, {} default.\n",
+        sections.total.groups,
+        sections.total.unused,
+        sections.total.unverified,
+        sections.total.default
+    );
+
+    out.push_str("## Regions\n\n");
+    table(
+        &mut out,
+        &[&["Account", "Region"][..], &SUMMARY_COLUMNS].concat(),
+        sections.regions.iter().map(|(key, summary)| {
+            itertools::chain(key.iter().map(|x| x.to_string()), summary_cells(summary)).collect()
+        }),
+    );
+
+    out.push_str("## VPCs\n\n");
+    table(
+        &mut out,
+        &[&["Account", "Region", "VPC"][..], &SUMMARY_COLUMNS].concat(),
+        sections.vpcs.iter().map(|(key, summary)| {
+            itertools::chain(key.iter().map(|x| x.to_string()), summary_cells(summary)).collect()
+        }),
+    );
+
+    out.push_str("## Coverage gaps\n\n");
+    if sections.gaps.is_empty() {
+        out.push_str("Every provider succeeded in every region.\n\n");
+    } else {
+        table(&mut out, &GAP_COLUMNS, sections.gaps.iter().map(gap_cells));
+    }
+
+    out.push_str("## Groups\n\n");
+    table(
+        &mut out,
+        &GROUP_COLUMNS,
+        sections.groups.iter().map(group_cells),
+    );
+    out
+}
+
+const STYLE: &str = "body{font-family:sans-serif;margin:2em}\
+table{border-collapse:collapse;margin-bottom:2em}\
+th,td{border:1px solid #ccc;padding:4px 8px;text-align:left;vertical-align:top}\
+th{background:#eee}table.sortable th{cursor:pointer}\
+tr.unused td{font-weight:bold}";
+
+/// Sorts a table by the clicked column, numerically when both cells are numbers.
+const SCRIPT: &str = "document.querySelectorAll('table.sortable th').forEach(function(th){\
+th.addEventListener('click',function(){\
+var i=th.cellIndex;var body=th.closest('table').tBodies[0];var asc=th.dataset.order!=='asc';th.dataset.order=asc?'asc':'desc';\
+Array.from(body.rows).sort(function(a,b){var x=a.cells[i].textContent,y=b.cells[i].textContent;\
+var c=(isNaN(x)||isNaN(y)||x===''||y==='')?x.localeCompare(y):x-y;return asc?c:-c;})\
+.forEach(function(row){body.appendChild(row);});});});";
+
+fn html(title: &str, sections: &Sections) -> String {
+    fn table(
+        out: &mut String,
+        class: &str,
+        header: &[&str],
+        rows: impl Iterator<Item = (bool, Vec<String>)>,
+    ) {
+        let _ = write!(out, "<table class=\"{}\"><thead><tr>", class);
+        for cell in header {
+            let _ = write!(out, "<th>{}</th>", escape(cell));
+        }
+        out.push_str("</tr></thead><tbody>\n");
+        for (highlight, row) in rows {
+            out.push_str(if highlight {
+                "<tr class=\"unused\">"
+            } else {
+                "<tr>"
+            });
+            for cell in row {
+                let _ = write!(out, "<td>{}</td>", escape(&cell));
+            }
+            out.push_str("</tr>\n");
+        }
+        out.push_str("</tbody></table>\n");
+    }
+
+    let mut out = String::new();
+    let _ = write!(
+        out,
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{0}</title>\
+         <style>{1}</style></head><body>\n<h1>{0}</h1>\n",
+        escape(title),
+        STYLE
+    );
+    let _ = writeln!(
+        out,
+        "<p>{} groups, {} unused, {} unverified, {} default.</p>",
+        sections.total.groups,
+        sections.total.unused,
+        sections.total.unverified,
+        sections.total.default
+    );
+
+    out.push_str("<h2>Regions</h2>\n");
+    table(
+        &mut out,
+        "sortable",
+        &[&["Account", "Region"][..], &SUMMARY_COLUMNS].concat(),
+        sections.regions.iter().map(|(key, summary)| {
+            let row = itertools::chain(key.iter().map(|x| x.to_string()), summary_cells(summary));
+            (false, row.collect())
+        }),
+    );
+
+    out.push_str("<h2>VPCs</h2>\n");
+    table(
+        &mut out,
+        "sortable",
+        &[&["Account", "Region", "VPC"][..], &SUMMARY_COLUMNS].concat(),
+        sections.vpcs.iter().map(|(key, summary)| {
+            let row = itertools::chain(key.iter().map(|x| x.to_string()), summary_cells(summary));
+            (false, row.collect())
+        }),
+    );
+
+    out.push_str("<h2>Coverage gaps</h2>\n");
+    if sections.gaps.is_empty() {
+        out.push_str("<p>Every provider succeeded in every region.</p>\n");
+    } else {
+        table(
+            &mut out,
+            "",
+            &GAP_COLUMNS,
+            sections.gaps.iter().map(|gap| (false, gap_cells(gap))),
+        );
+    }
+
+    out.push_str("<h2>Groups</h2>\n");
+    table(
+        &mut out,
+        "sortable",
+        &GROUP_COLUMNS,
+        sections.groups.iter().map(|record| {
+            (
+                record.unused && record.coverage_complete,
+                group_cells(record),
+            )
+        }),
+    );
+
+    let _ = write!(out, "<script>{}</script>\n</body></html>\n", SCRIPT);
+    out
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod test {
+    use super::{render, Format};
+    use crate::security::{Coverage, ExistingGroup, SecurityGroups};
+
+    fn groups() -> SecurityGroups {
+        let group = |group_id: &str, vpc_id: &str| ExistingGroup {
+            region: "eu-west-1".to_string(),
+            group_id: group_id.to_string(),
+            group_name: format!("<{}>", group_id),
+            vpc_id: Some(vpc_id.to_string()),
+            ..ExistingGroup::default()
+        };
+        let mut groups = SecurityGroups {
+            existing_groups: vec![
+                group("sg-1", "vpc-1"),
+                group("sg-2", "vpc-1"),
+                group("sg-3", "vpc-2"),
+            ],
+            external_references: [("sg-1".to_string(), vec!["ec2@eu-west-1".into()])]
+                .into_iter()
+                .collect(),
+            ..SecurityGroups::default()
+        };
+        groups.set_account("1");
+        groups.record_coverage("1", "eu-west-1", "ec2", Coverage::Complete);
+        groups.record_coverage(
+            "1",
+            "eu-west-1",
+            "rds",
+            Coverage::Failed("denied".to_string()),
+        );
+        groups
+    }
+
+    #[test]
+    fn test_markdown() {
+        let report = render(&groups(), Format::Markdown, "Security groups");
+        assert!(
+            report.starts_with("# Security groups\n\n3 groups, 0 unused, 2 unverified, 0 default.")
+        );
+        assert!(report.contains("| 1 | eu-west-1 | 3 | 0 | 2 | 0 |"));
+        assert!(report.contains("| 1 | eu-west-1 | vpc-1 | 2 | 0 | 1 | 0 |"));
+        assert!(report.contains("| 1 | eu-west-1 | rds | denied |"));
+        assert!(report.contains("| sg-1 | &lt;sg-1&gt; | no | incomplete | 1/ec2@eu-west-1 |"));
+    }
+
+    #[test]
+    fn test_html() {
+        let mut groups = groups();
+        let report = render(&groups, Format::Html, "Security groups");
+        assert!(report.starts_with("<!DOCTYPE html>"));
+        assert!(report.contains("<td>&lt;sg-2&gt;</td>"));
+        assert!(report.contains("<td>rds</td><td>denied</td>"));
+        assert!(report.contains("<script>"));
+        // Unused groups are only highlighted once nothing could still use them.
+        assert!(!report.contains("<tr class=\"unused\">"));
+
+        groups.record_coverage("1", "eu-west-1", "rds", Coverage::Complete);
+        let report = render(&groups, Format::Html, "Security groups");
+        assert!(report.contains("<p>3 groups, 2 unused, 0 unverified, 0 default.</p>"));
+        assert!(report.contains(
+            "<tr class=\"unused\"><td>1</td><td>eu-west-1</td><td>vpc-1</td><td>sg-2</td>"
+        ));
+    }
+}
@@ -0,0 +1,132 @@
+use std::sync::Arc;
+
+use log::{info, warn};
+
+use crate::backend::Backend;
+use crate::cache::ResponseCache;
+use crate::error::ProviderError;
+use crate::provider::{SecurityGroupsProvider, BUILTIN_PROVIDERS};
+use crate::security::{Coverage, SecurityGroups};
+use crate::throttle::Throttle;
+
+/// The result of a scan. Groups in regions where a provider failed are still
+/// returned, see [`SecurityGroups::is_region_complete`].
+#[derive(Default)]
+pub struct ScanReport {
+    pub groups: SecurityGroups,
+    pub failures: Vec<ProviderError>,
+    /// Selected regions, by account, that were skipped because they are not enabled for
+    /// the account.
+    pub disabled_regions: Vec<(String, String)>,
+    /// Accounts of the organization that could not be scanned.
+    pub failed_accounts: Vec<String>,
+}
+
+impl ScanReport {
+    pub fn merge(&mut self, other: ScanReport) {
+        self.groups.merge(&other.groups);
+        self.failures.extend(other.failures);
+        self.disabled_regions.extend(other.disabled_regions);
+        self.failed_accounts.extend(other.failed_accounts);
+    }
+
+    pub fn log_failures(&self) {
+        for account_id in self.failed_accounts.iter() {
+            warn!("{}: account not scanned", account_id);
+        }
+        for (account_id, region) in self.disabled_regions.iter() {
+            warn!(
+                "{}: opt-in region not enabled for account {}, skipped",
+                region, account_id
+            );
+        }
+        for failure in self.failures.iter() {
+            warn!("{}", failure);
+        }
+        for reference in self.groups.dangling_references.iter() {
+            warn!(
+                "{}: unresolved group name {} in {}",
+                reference.source,
+                reference.group_name,
+                reference.vpc_id.as_deref().unwrap_or(&reference.region)
+            );
+        }
+    }
+}
+
+/// Runs `providers` against one region of `account_id`. Built-in providers not among
+/// them are recorded as skipped. Name references are left unresolved until all
+/// regions are merged.
+pub async fn scan_region(
+    account_id: &str,
+    backend: &dyn Backend,
+    providers: &[Arc<dyn SecurityGroupsProvider>],
+    throttle: &Throttle,
+    cache: Option<&ResponseCache>,
+) -> ScanReport {
+    let region = backend.region();
+    let skipped = skipped_providers(account_id, &region, providers);
+    let res = futures::future::join_all(providers.iter().map(|provider| async {
+        let res = load_provider(account_id, provider.as_ref(), backend, throttle, cache).await;
+        (provider.name(), res)
+    }))
+    .await
+    .into_iter()
+    .fold(skipped, |mut acc, (provider, item)| {
+        match item {
+            Ok(mut groups) => {
+                groups.set_account(account_id);
+                acc.groups.merge(&groups);
+                acc.groups
+                    .record_coverage(account_id, &region, provider, Coverage::Complete);
+            }
+            Err(err) => {
+                acc.groups.record_coverage(
+                    account_id,
+                    &region,
+                    provider,
+                    Coverage::Failed(err.to_string()),
+                );
+                acc.failures.push(err);
+            }
+        }
+        acc
+    });
+
+    info!("{} of {} loaded", region, account_id);
+    res
+}
+
+fn skipped_providers(
+    account_id: &str,
+    region: &str,
+    providers: &[Arc<dyn SecurityGroupsProvider>],
+) -> ScanReport {
+    let mut report = ScanReport::default();
+    for name in BUILTIN_PROVIDERS {
+        if !providers.iter().any(|x| x.name() == *name) {
+            report
+                .groups
+                .record_coverage(account_id, region, name, Coverage::Skipped);
+        }
+    }
+    report
+}
+
+async fn load_provider(
+    account_id: &str,
+    provider: &dyn SecurityGroupsProvider,
+    backend: &dyn Backend,
+    throttle: &Throttle,
+    cache: Option<&ResponseCache>,
+) -> Result<SecurityGroups, ProviderError> {
+    let region = backend.region();
+    if let Some(groups) = cache.and_then(|cache| cache.get(account_id, &region, provider.name())) {
+        return Ok(groups);
+    }
+    let res = throttle.run(provider.load(backend)).await;
+    if let (Some(cache), Ok(groups)) = (cache, &res) {
+        cache.put(account_id, &region, provider.name(), groups);
+    }
+    res
+}
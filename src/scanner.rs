@@ -0,0 +1,378 @@
+use std::sync::Arc;
+
+use anyhow::Context;
+use aws_config::meta::region::ProvideRegion;
+use aws_sdk_ec2::Region;
+use aws_types::credentials::{ProvideCredentials, SharedCredentialsProvider};
+use itertools::Itertools;
+use log::warn;
+use tokio::sync::OnceCell;
+
+use crate::accounts::{assume_role, resolve_accounts, Account, AssumeRole};
+use crate::backend::AwsBackend;
+use crate::cache::ResponseCache;
+use crate::client::{ClientConfig, ClientOptions, Endpoints};
+use crate::protection::Protection;
+use crate::provider::{Registry, SecurityGroupsProvider};
+use crate::regions::{load_regions, RegionFilter};
+use crate::scan::{scan_region, ScanReport};
+use crate::throttle::{Throttle, ThrottleConfig};
+
+/// Scans AWS accounts for security groups and the services referencing them.
+///
+/// ```no_run
+/// # async fn run() -> anyhow::Result<()> {
+/// let scanner = aws_sg_cleanup::Scanner::builder()
+///     .regions(["eu-west-1", "us-east-1"])
+///     .max_concurrency(4)
+///     .build()?;
+/// let report = scanner.scan().await?;
+/// for group in report.groups.find_unused() {
+///     if report.groups.is_region_complete(&group.account_id, &group.region) {
+///         println!("{} {}", group.region, group.group_id);
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct Scanner {
+    regions: Option<Vec<String>>,
+    region_filter: RegionFilter,
+    providers: Vec<Arc<dyn SecurityGroupsProvider>>,
+    options: ClientOptions,
+    organization_role: Option<String>,
+    accounts: OnceCell<Vec<Account>>,
+    throttle: Arc<Throttle>,
+    cache: Option<ResponseCache>,
+    protection: Protection,
+}
+
+pub struct ScannerBuilder {
+    regions: Option<Vec<String>>,
+    region_filter: RegionFilter,
+    registry: Registry,
+    providers: Vec<String>,
+    skip_providers: Vec<String>,
+    options: ClientOptions,
+    assume_role: Option<AssumeRole>,
+    organization_role: Option<String>,
+    throttle: ThrottleConfig,
+    cache: Option<ResponseCache>,
+    protection: Protection,
+}
+
+impl Default for ScannerBuilder {
+    fn default() -> Self {
+        Self {
+            regions: None,
+            region_filter: RegionFilter::default(),
+            registry: Registry::builtin(),
+            providers: vec![],
+            skip_providers: vec![],
+            options: ClientOptions::default(),
+            assume_role: None,
+            organization_role: None,
+            throttle: ThrottleConfig::default(),
+            cache: None,
+            protection: Protection::default(),
+        }
+    }
+}
+
+impl ScannerBuilder {
+    /// Regions to scan, every region enabled for the account by default.
+    pub fn regions<I, S>(mut self, regions: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.regions = Some(regions.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Narrows the regions to scan by name and opt-in status.
+    pub fn region_filter(mut self, filter: RegionFilter) -> Self {
+        self.region_filter = filter;
+        self
+    }
+
+    /// Providers to choose from, [`Registry::builtin`] by default.
+    pub fn registry(mut self, registry: Registry) -> Self {
+        self.registry = registry;
+        self
+    }
+
+    /// Providers to run by name, every registered one by default.
+    pub fn providers<I, S>(mut self, providers: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.providers = providers.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Providers not to run by name.
+    pub fn skip_providers<I, S>(mut self, providers: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.skip_providers = providers.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Credentials for every call, the default provider chain otherwise.
+    pub fn credentials_provider(mut self, credentials: impl ProvideCredentials + 'static) -> Self {
+        self.options.credentials = Some(SharedCredentialsProvider::new(credentials));
+        self
+    }
+
+    /// Shared config profile for credentials and the default region.
+    pub fn profile(mut self, profile: impl Into<String>) -> Self {
+        self.options.profile = Some(profile.into());
+        self
+    }
+
+    /// Role to assume with the configured credentials before scanning.
+    pub fn assume_role(mut self, role_arn: impl Into<String>, external_id: Option<String>) -> Self {
+        self.assume_role = Some(AssumeRole {
+            role_arn: role_arn.into(),
+            external_id,
+        });
+        self
+    }
+
+    /// Scans every active account of the organization by assuming `role_name` in it,
+    /// such as `OrganizationAccountAccessRole`. Needs `organizations:ListAccounts`.
+    pub fn organization(mut self, role_name: impl Into<String>) -> Self {
+        self.organization_role = Some(role_name.into());
+        self
+    }
+
+    pub fn endpoints(mut self, endpoints: Endpoints) -> Self {
+        self.options.endpoints = endpoints;
+        self
+    }
+
+    pub fn client_options(mut self, options: ClientOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Maximum number of provider scans running at the same time across all regions.
+    pub fn max_concurrency(mut self, max_concurrency: usize) -> Self {
+        self.throttle.max_concurrency = max_concurrency;
+        self
+    }
+
+    pub fn throttle(mut self, throttle: ThrottleConfig) -> Self {
+        self.throttle = throttle;
+        self
+    }
+
+    pub fn cache(mut self, cache: ResponseCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Protection rules set on the scanned groups, see
+    /// [`find_unused`](crate::SecurityGroups::find_unused).
+    pub fn protection(mut self, protection: Protection) -> Self {
+        self.protection = protection;
+        self
+    }
+
+    pub fn build(self) -> anyhow::Result<Scanner> {
+        let providers = self
+            .registry
+            .select(&self.providers, &self.skip_providers)?;
+        let options = match self.assume_role {
+            Some(role) => assume_role(&self.options, role),
+            None => self.options,
+        };
+        Ok(Scanner {
+            regions: self.regions,
+            region_filter: self.region_filter,
+            providers,
+            options,
+            organization_role: self.organization_role,
+            accounts: OnceCell::new(),
+            throttle: Arc::new(Throttle::new(self.throttle)),
+            cache: self.cache,
+            protection: self.protection,
+        })
+    }
+}
+
+impl Scanner {
+    pub fn builder() -> ScannerBuilder {
+        ScannerBuilder::default()
+    }
+
+    /// Scans every selected region of every account and resolves name references
+    /// across them. Provider failures do not fail the scan, they are reported in
+    /// [`ScanReport::failures`] and in the coverage of the returned groups. Selected
+    /// regions an account has not enabled are skipped and listed in
+    /// [`ScanReport::disabled_regions`]. In organization mode, accounts that cannot be
+    /// reached are listed in [`ScanReport::failed_accounts`].
+    pub async fn scan(&self) -> anyhow::Result<ScanReport> {
+        let accounts = self.accounts().await?;
+        let reports = futures::future::join_all(accounts.iter().map(|account| async move {
+            match self.scan_account(account).await {
+                Err(err) if self.organization_role.is_some() => {
+                    warn!("{}: {:#}", account.account_id, err);
+                    Ok(ScanReport {
+                        failed_accounts: vec![account.account_id.clone()],
+                        ..ScanReport::default()
+                    })
+                }
+                res => res,
+            }
+        }))
+        .await;
+
+        let mut report = ScanReport::default();
+        for item in reports {
+            report.merge(item?);
+        }
+        report.groups.resolve_names();
+        report.groups.protection = self.protection.clone();
+        Ok(report)
+    }
+
+    async fn scan_account(&self, account: &Account) -> anyhow::Result<ScanReport> {
+        let (regions, disabled_regions) = match &self.regions {
+            Some(regions) => (
+                regions
+                    .iter()
+                    .filter(|x| self.region_filter.matches(x))
+                    .cloned()
+                    .collect(),
+                vec![],
+            ),
+            None => {
+                let (enabled, disabled) = self
+                    .region_filter
+                    .select(load_regions(&account.options).await?);
+                (
+                    enabled.into_iter().map(|x| x.name).collect_vec(),
+                    disabled
+                        .into_iter()
+                        .map(|x| (account.account_id.clone(), x.name))
+                        .collect_vec(),
+                )
+            }
+        };
+        let regions = regions.into_iter().map(|region| async move {
+            let config = ClientConfig::load(
+                Region::new(region),
+                &account.options,
+                self.throttle.retry_config(),
+            )
+            .await;
+            scan_region(
+                &account.account_id,
+                &AwsBackend::new(&config).with_throttle(self.throttle.clone()),
+                &self.providers,
+                &self.throttle,
+                self.cache.as_ref(),
+            )
+            .await
+        });
+
+        Ok(futures::future::join_all(regions).await.into_iter().fold(
+            ScanReport {
+                disabled_regions,
+                ..ScanReport::default()
+            },
+            |mut acc, item| {
+                acc.merge(item);
+                acc
+            },
+        ))
+    }
+
+    /// The accounts to scan, resolved on first use.
+    pub async fn accounts(&self) -> anyhow::Result<&[Account]> {
+        let accounts = self
+            .accounts
+            .get_or_try_init(|| resolve_accounts(&self.options, self.organization_role.as_deref()))
+            .await?;
+        Ok(accounts)
+    }
+
+    /// Configuration for `region` of `account_id`, one of [`Scanner::accounts`].
+    pub async fn account_client_config(
+        &self,
+        account_id: &str,
+        region: &str,
+    ) -> anyhow::Result<ClientConfig> {
+        let account = self
+            .accounts()
+            .await?
+            .iter()
+            .find(|x| x.account_id == account_id)
+            .with_context(|| format!("account {} was not scanned", account_id))?;
+        Ok(ClientConfig::load(
+            Region::new(region.to_owned()),
+            &account.options,
+            self.throttle.retry_config(),
+        )
+        .await)
+    }
+
+    /// Configuration for the credentials of the scanner, outside any scanned account.
+    pub async fn client_config(&self, region: impl ProvideRegion + 'static) -> ClientConfig {
+        ClientConfig::load(region, &self.options, self.throttle.retry_config()).await
+    }
+
+    pub fn region_filter(&self) -> &RegionFilter {
+        &self.region_filter
+    }
+
+    pub fn protection(&self) -> &Protection {
+        &self.protection
+    }
+
+    pub fn providers(&self) -> &[Arc<dyn SecurityGroupsProvider>] {
+        &self.providers
+    }
+
+    pub fn client_options(&self) -> &ClientOptions {
+        &self.options
+    }
+
+    /// Limits shared by the scan, for follow-up calls such as the cleanup.
+    pub fn throttle(&self) -> &Arc<Throttle> {
+        &self.throttle
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Scanner;
+    use crate::provider::Registry;
+
+    #[test]
+    fn test_build_providers() {
+        let names = |scanner: Scanner| {
+            scanner
+                .providers()
+                .iter()
+                .map(|x| x.name())
+                .collect::<Vec<_>>()
+        };
+        let scanner = Scanner::builder().build().unwrap();
+        assert_eq!(names(scanner), Registry::builtin().names());
+
+        let scanner = Scanner::builder()
+            .providers(["ec2-security-groups"])
+            .build()
+            .unwrap();
+        assert_eq!(names(scanner), ["ec2-security-groups"]);
+
+        assert!(Scanner::builder().providers(["s3"]).build().is_err());
+        assert!(Scanner::builder().skip_providers(["s3"]).build().is_err());
+    }
+}
@@ -1,151 +1,521 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
 
 use async_trait::async_trait;
-use aws_sdk_ec2::Client;
-use aws_types::SdkConfig;
-use futures::StreamExt;
 use itertools::Itertools;
 use maplit::hashmap;
+use serde::{Deserialize, Serialize};
 
-#[async_trait]
-pub trait SecurityGroupsProvider<T> {
-    async fn load(config: &T) -> SecurityGroups;
-}
+use crate::backend::{self, Backend};
+use crate::error::ProviderError;
+use crate::graph::ReferenceGraph;
+use crate::protection::Protection;
+use crate::provider::SecurityGroupsProvider;
 
 type ReferenceServiceName = String;
 type GroupId = String;
+type AccountId = String;
+type RegionName = String;
+type ProviderName = String;
 
-#[derive(Default, Debug, Clone)]
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct SecurityGroups {
-    pub external_references: HashMap<GroupId, Vec<ReferenceServiceName>>,
+    pub external_references: HashMap<GroupId, Vec<ServiceReference>>,
     pub existing_groups: Vec<ExistingGroup>,
+    pub coverage: HashMap<AccountId, HashMap<RegionName, HashMap<ProviderName, Coverage>>>,
+    /// References by group name, waiting for [`SecurityGroups::resolve_names`].
+    pub name_references: Vec<NameReference>,
+    /// Name references that matched no group.
+    pub dangling_references: Vec<NameReference>,
+    /// The resources behind `external_references`, used to explain why a group is used.
+    #[serde(default)]
+    pub attachments: HashMap<GroupId, Vec<Attachment>>,
+    /// Configured rather than scanned, so never saved with an inventory.
+    #[serde(skip)]
+    pub protection: Protection,
+}
+
+/// A service holding a group, such as `ec2@eu-west-1`, in the account it runs in.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct ServiceReference {
+    pub account_id: AccountId,
+    pub source: ReferenceServiceName,
+}
+
+impl From<&str> for ServiceReference {
+    fn from(source: &str) -> Self {
+        source.to_owned().into()
+    }
+}
+
+impl From<String> for ServiceReference {
+    fn from(source: String) -> Self {
+        Self {
+            account_id: String::new(),
+            source,
+        }
+    }
+}
+
+impl fmt::Display for ServiceReference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.account_id.is_empty() {
+            write!(f, "{}", self.source)
+        } else {
+            write!(f, "{}/{}", self.account_id, self.source)
+        }
+    }
+}
+
+/// A resource holding a group, such as `instance i-1`, and the service that found it.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Attachment {
+    pub reference: ServiceReference,
+    pub resource: String,
+}
+
+/// A reference to a group by name rather than ID, as used by EC2-Classic style
+/// ElastiCache cache security groups and RDS DB security groups.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NameReference {
+    #[serde(default)]
+    pub account_id: AccountId,
+    pub region: String,
+    pub vpc_id: Option<String>,
+    pub group_name: String,
+    pub source: ReferenceServiceName,
+    /// Resources using the name, such as `DB instance db-1`.
+    #[serde(default)]
+    pub resources: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Coverage {
+    Complete,
+    Failed(String),
+    /// The provider was not selected or not compiled in, so its references are unknown.
+    Skipped,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ExistingGroup {
+    #[serde(default)]
+    pub account_id: AccountId,
     pub region: String,
     pub group_id: GroupId,
+    pub vpc_id: Option<String>,
+    pub owner_id: Option<String>,
     pub group_name: String,
     pub group_description: String,
+    /// Every VPC has a group named `default` that AWS refuses to delete.
+    pub is_default: bool,
+    pub tags: BTreeMap<String, String>,
+    pub ingress: Vec<Rule>,
+    pub egress: Vec<Rule>,
+    /// Groups referenced by ingress or egress rules, excluding the group itself. EC2
+    /// refuses to delete a group while any rule of another group references it.
     pub references: HashSet<String>,
 }
 
+/// A single rule entry: one protocol and port range allowing one peer.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rule {
+    /// IP protocol name or number, `-1` for all protocols.
+    pub protocol: String,
+    pub from_port: Option<i32>,
+    pub to_port: Option<i32>,
+    pub peer: RulePeer,
+    pub description: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RulePeer {
+    Cidr(String),
+    CidrV6(String),
+    PrefixList(String),
+    Group {
+        group_id: String,
+        user_id: Option<String>,
+    },
+}
+
+impl Default for RulePeer {
+    fn default() -> Self {
+        Self::Cidr(String::new())
+    }
+}
+
+impl fmt::Display for RulePeer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Cidr(cidr) | Self::CidrV6(cidr) => write!(f, "{}", cidr),
+            Self::PrefixList(prefix_list_id) => write!(f, "{}", prefix_list_id),
+            Self::Group {
+                group_id,
+                user_id: Some(user_id),
+            } => write!(f, "{}/{}", user_id, group_id),
+            Self::Group { group_id, .. } => write!(f, "{}", group_id),
+        }
+    }
+}
+
+impl Rule {
+    pub fn protocol_name(&self) -> &str {
+        match self.protocol.as_str() {
+            "-1" => "all",
+            protocol => protocol,
+        }
+    }
+
+    pub fn ports(&self) -> String {
+        match (self.from_port, self.to_port) {
+            _ if self.protocol == "-1" => "all".to_string(),
+            (Some(-1), _) | (None, None) => "all".to_string(),
+            (Some(from), Some(to)) if from == to => from.to_string(),
+            (Some(from), Some(to)) => format!("{}-{}", from, to),
+            (Some(port), None) | (None, Some(port)) => port.to_string(),
+        }
+    }
+
+    pub(crate) fn from_permissions(
+        permissions: Vec<aws_sdk_ec2::model::IpPermission>,
+    ) -> Vec<Self> {
+        permissions
+            .into_iter()
+            .flat_map(|permission| {
+                let aws_sdk_ec2::model::IpPermission {
+                    ip_protocol,
+                    from_port,
+                    to_port,
+                    ip_ranges,
+                    ipv6_ranges,
+                    prefix_list_ids,
+                    user_id_group_pairs,
+                    ..
+                } = permission;
+                let cidrs = ip_ranges
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter_map(|x| Some((RulePeer::Cidr(x.cidr_ip?), x.description)));
+                let cidrs_v6 = ipv6_ranges
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter_map(|x| Some((RulePeer::CidrV6(x.cidr_ipv6?), x.description)));
+                let prefix_lists = prefix_list_ids
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter_map(|x| Some((RulePeer::PrefixList(x.prefix_list_id?), x.description)));
+                let groups = user_id_group_pairs
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter_map(|x| {
+                        let peer = RulePeer::Group {
+                            group_id: x.group_id?,
+                            user_id: x.user_id,
+                        };
+                        Some((peer, x.description))
+                    });
+                let protocol = ip_protocol.unwrap_or_else(|| "-1".to_string());
+                cidrs
+                    .chain(cidrs_v6)
+                    .chain(prefix_lists)
+                    .chain(groups)
+                    .map(move |(peer, description)| Rule {
+                        protocol: protocol.clone(),
+                        from_port,
+                        to_port,
+                        peer,
+                        description,
+                    })
+                    .collect_vec()
+            })
+            .collect()
+    }
+}
+
 pub struct AWSSecurityGroups {}
 
 #[async_trait]
-impl SecurityGroupsProvider<SdkConfig> for AWSSecurityGroups {
-    async fn load(config: &SdkConfig) -> SecurityGroups {
-        let client = Client::new(config);
-        let existing_groups = client
+impl SecurityGroupsProvider for AWSSecurityGroups {
+    fn name(&self) -> &'static str {
+        "ec2-security-groups"
+    }
+
+    fn description(&self) -> &'static str {
+        "Security groups themselves and the rules referencing other groups"
+    }
+
+    fn iam_actions(&self) -> &'static [&'static str] {
+        &["ec2:DescribeSecurityGroups"]
+    }
+
+    async fn load(&self, backend: &dyn Backend) -> Result<SecurityGroups, ProviderError> {
+        let region = backend.region();
+        let groups = backend
             .describe_security_groups()
-            .into_paginator()
-            .items()
-            .send()
-            .map(|item| {
-                let group = item.unwrap();
+            .await
+            .map_err(|err| ProviderError::new(self.name(), &region, err))?;
 
-                let aws_sdk_ec2::model::SecurityGroup {
+        let existing_groups = groups
+            .into_iter()
+            .map(|group| {
+                let backend::SecurityGroup {
                     group_id,
+                    vpc_id,
+                    owner_id,
                     group_name,
                     description,
-                    ip_permissions,
-                    ..
+                    tags,
+                    ingress,
+                    egress,
                 } = group;
-                let group_id = group_id.unwrap();
-                let group_name = group_name.unwrap();
-                let group_description = description.unwrap();
-
-                let references: HashSet<_> = ip_permissions
-                    .unwrap_or_default()
+                let is_default = group_name == "default";
+                let references: HashSet<_> = ingress
                     .iter()
-                    .flat_map(|permission| {
-                        permission
-                            .user_id_group_pairs()
-                            .unwrap_or_default()
-                            .iter()
-                            .map(|x| x.group_id().unwrap().to_owned())
+                    .chain(egress.iter())
+                    .filter_map(|rule| match &rule.peer {
+                        RulePeer::Group { group_id, .. } => Some(group_id.clone()),
+                        _ => None,
                     })
                     .filter(|x| *x != group_id)
                     .collect();
 
                 ExistingGroup {
-                    region: config.region().unwrap().to_string(),
+                    account_id: String::new(),
+                    region: region.clone(),
                     group_id,
+                    vpc_id,
+                    owner_id,
                     group_name,
-                    group_description,
+                    group_description: description,
+                    is_default,
+                    tags,
+                    ingress,
+                    egress,
                     references,
                 }
             })
-            .collect::<Vec<_>>()
-            .await
-            .into_iter()
-            .filter(|group| group.group_description != "default VPC security group")
             .collect_vec();
 
-        SecurityGroups {
-            external_references: hashmap![],
+        Ok(SecurityGroups {
             existing_groups,
-        }
+            ..SecurityGroups::default()
+        })
     }
 }
 
 impl SecurityGroups {
+    /// References from `source` to every group of `group_ids`, without an account until
+    /// [`SecurityGroups::set_account`].
     pub fn create_from_group_ids(source: String, group_ids: impl Iterator<Item = String>) -> Self {
-        let mut external_references = hashmap![];
+        let mut external_references: HashMap<GroupId, Vec<ServiceReference>> = hashmap![];
         for item in group_ids.unique() {
             external_references
                 .entry(item)
-                .or_insert_with(Vec::new)
-                .push(source.clone());
+                .or_default()
+                .push(source.clone().into());
         }
         Self {
             external_references,
-            existing_groups: vec![],
+            ..Self::default()
+        }
+    }
+
+    /// References from `source` to the groups of every resource, keeping the resources as
+    /// [`Attachment`]s.
+    pub fn create_from_resources(
+        source: String,
+        resources: impl Iterator<Item = (String, Vec<String>)>,
+    ) -> Self {
+        let mut attachments: HashMap<GroupId, Vec<Attachment>> = hashmap![];
+        let mut group_ids = vec![];
+        for (resource, ids) in resources {
+            for group_id in ids.into_iter().unique() {
+                attachments
+                    .entry(group_id.clone())
+                    .or_default()
+                    .push(Attachment {
+                        reference: source.clone().into(),
+                        resource: resource.clone(),
+                    });
+                group_ids.push(group_id);
+            }
+        }
+        Self {
+            attachments,
+            ..Self::create_from_group_ids(source, group_ids.into_iter())
+        }
+    }
+
+    /// Attributes every group and reference to `account_id`.
+    pub fn set_account(&mut self, account_id: &str) {
+        for group in self.existing_groups.iter_mut() {
+            group.account_id = account_id.to_owned();
+        }
+        for reference in self
+            .name_references
+            .iter_mut()
+            .chain(self.dangling_references.iter_mut())
+        {
+            reference.account_id = account_id.to_owned();
+        }
+        for reference in self.external_references.values_mut().flatten() {
+            reference.account_id = account_id.to_owned();
         }
+        for attachment in self.attachments.values_mut().flatten() {
+            attachment.reference.account_id = account_id.to_owned();
+        }
+    }
+
+    pub fn record_coverage(
+        &mut self,
+        account_id: &str,
+        region: &str,
+        provider: &str,
+        coverage: Coverage,
+    ) {
+        self.coverage
+            .entry(account_id.to_owned())
+            .or_default()
+            .entry(region.to_owned())
+            .or_default()
+            .insert(provider.to_owned(), coverage);
+    }
+
+    /// Providers that failed to load or were skipped in `region` of `account_id`, with
+    /// the reason.
+    pub fn coverage_gaps(&self, account_id: &str, region: &str) -> Vec<(&str, &str)> {
+        self.coverage
+            .get(account_id)
+            .and_then(|regions| regions.get(region))
+            .into_iter()
+            .flatten()
+            .filter_map(|(provider, coverage)| match coverage {
+                Coverage::Complete => None,
+                Coverage::Failed(reason) => Some((provider.as_str(), reason.as_str())),
+                Coverage::Skipped => Some((provider.as_str(), "skipped")),
+            })
+            .sorted()
+            .collect_vec()
+    }
+
+    /// A region is complete when it was scanned and every provider succeeded in it.
+    /// Only groups in complete regions can safely be considered unused.
+    pub fn is_region_complete(&self, account_id: &str, region: &str) -> bool {
+        self.coverage
+            .get(account_id)
+            .is_some_and(|regions| regions.contains_key(region))
+            && self.coverage_gaps(account_id, region).is_empty()
     }
 
     pub fn merge(&mut self, other: &SecurityGroups) {
         for (group_id, references) in other.external_references.iter() {
             self.external_references
                 .entry(group_id.clone())
-                .or_insert_with(Vec::new)
+                .or_default()
                 .extend(references.clone());
         }
+        for (group_id, attachments) in other.attachments.iter() {
+            self.attachments
+                .entry(group_id.clone())
+                .or_default()
+                .extend(attachments.clone());
+        }
         self.existing_groups.extend(other.existing_groups.clone());
+        self.name_references.extend(other.name_references.clone());
+        self.dangling_references
+            .extend(other.dangling_references.clone());
+        for (account_id, regions) in other.coverage.iter() {
+            let coverage = self.coverage.entry(account_id.clone()).or_default();
+            for (region, providers) in regions.iter() {
+                coverage
+                    .entry(region.clone())
+                    .or_default()
+                    .extend(providers.clone());
+            }
+        }
     }
 
-    pub fn collect_referencing_services(
-        &self,
-        group_id: &str,
-        visited: HashSet<String>,
-    ) -> HashSet<String> {
-        let direct_refs = self
-            .external_references
-            .get(group_id)
-            .map_or(vec![], |x| x.clone());
+    /// Drops the groups, coverage and name references of regions `keep` rejects.
+    pub fn retain_regions(&mut self, keep: impl Fn(&str) -> bool) {
+        self.existing_groups.retain(|x| keep(&x.region));
+        for regions in self.coverage.values_mut() {
+            regions.retain(|region, _| keep(region));
+        }
+        self.name_references.retain(|x| keep(&x.region));
+        self.dangling_references.retain(|x| keep(&x.region));
+    }
 
-        let mut visited = visited;
-        visited.insert(group_id.to_string());
+    /// Turns name references into ID references. A name matches groups in the same
+    /// account and region and, when the reference knows its VPC, the same VPC. Without a VPC an
+    /// EC2-Classic group is preferred. A name still matching several groups marks all
+    /// of them as used, since any could be the one in use. Names matching no group are
+    /// moved to `dangling_references`.
+    pub fn resolve_names(&mut self) {
+        for reference in std::mem::take(&mut self.name_references) {
+            let candidates = self
+                .existing_groups
+                .iter()
+                .filter(|group| {
+                    group.account_id == reference.account_id
+                        && group.region == reference.region
+                        && group.group_name == reference.group_name
+                })
+                .filter(|group| reference.vpc_id.is_none() || group.vpc_id == reference.vpc_id)
+                .collect_vec();
+            let classic = candidates
+                .iter()
+                .filter(|group| group.vpc_id.is_none())
+                .collect_vec();
+            let resolved = match classic.as_slice() {
+                [group] if reference.vpc_id.is_none() => vec![group.group_id.clone()],
+                _ => candidates
+                    .iter()
+                    .map(|group| group.group_id.clone())
+                    .collect_vec(),
+            };
+            if resolved.is_empty() {
+                self.dangling_references.push(reference);
+                continue;
+            }
+            let source = ServiceReference {
+                account_id: reference.account_id.clone(),
+                source: reference.source.clone(),
+            };
+            for group_id in resolved {
+                self.attachments
+                    .entry(group_id.clone())
+                    .or_default()
+                    .extend(reference.resources.iter().map(|resource| Attachment {
+                        reference: source.clone(),
+                        resource: resource.clone(),
+                    }));
+                let sources = self.external_references.entry(group_id).or_default();
+                if !sources.contains(&source) {
+                    sources.push(source.clone());
+                }
+            }
+        }
+    }
 
-        let indirect_refs = self
-            .existing_groups
-            .iter()
-            .filter(|group| {
-                group.references.contains(group_id) && !visited.contains(&group.group_id)
-            })
-            .flat_map(|group| self.collect_referencing_services(&group.group_id, visited.clone()));
+    pub fn reference_graph(&self) -> ReferenceGraph<'_> {
+        ReferenceGraph::new(self)
+    }
 
-        itertools::chain(direct_refs, indirect_refs).collect()
+    /// Why `group` is protected from cleanup, if it is.
+    pub fn protection_reason(&self, group: &ExistingGroup) -> Option<String> {
+        self.protection.reason(group)
     }
 
+    /// Groups no service uses, leaving out default and protected groups.
     pub fn find_unused(&self) -> Vec<&ExistingGroup> {
+        let graph = self.reference_graph();
         self.existing_groups
             .iter()
-            .filter(|group| {
-                let referenced_by =
-                    self.collect_referencing_services(&group.group_id, HashSet::new());
-                referenced_by.is_empty()
-            })
+            .filter(|group| !group.is_default && !graph.is_used(&group.group_id))
+            .filter(|group| self.protection.reason(group).is_none())
             .collect_vec()
     }
 }
@@ -153,12 +523,11 @@ impl SecurityGroups {
 #[cfg(test)]
 mod test {
 
-    use std::collections::HashSet;
-
     use itertools::Itertools;
     use maplit::hashmap;
 
-    use crate::security::ExistingGroup;
+    use crate::protection::Protection;
+    use crate::security::{Coverage, ExistingGroup, NameReference, Rule, ServiceReference};
 
     use super::SecurityGroups;
 
@@ -174,28 +543,30 @@ mod test {
     fn test_merge() {
         let sg1 = SecurityGroups {
             external_references: hashmap![
-                "1".to_string() => vec!["a".to_string(), "b".to_string()],
+                "1".to_string() => vec!["a".into(), "b".into()],
             ],
             existing_groups: vec![ExistingGroup {
                 region: "1".to_string(),
                 group_id: "1".to_string(),
                 group_name: "1".to_string(),
                 group_description: "1".to_string(),
-                references: HashSet::default(),
+                ..ExistingGroup::default()
             }],
+            ..SecurityGroups::default()
         };
         let sg2 = SecurityGroups {
             external_references: hashmap![
-                "1".to_string() => vec!["c".to_string()],
-                "2".to_string() => vec!["e".to_string()]
+                "1".to_string() => vec!["c".into()],
+                "2".to_string() => vec!["e".into()]
             ],
             existing_groups: vec![ExistingGroup {
                 region: "2".to_string(),
                 group_id: "2".to_string(),
                 group_name: "2".to_string(),
                 group_description: "2".to_string(),
-                references: HashSet::default(),
+                ..ExistingGroup::default()
             }],
+            ..SecurityGroups::default()
         };
         let mut sg = sg1.clone();
         sg.merge(&sg2);
@@ -203,8 +574,8 @@ mod test {
         assert_eq!(
             sg.external_references,
             hashmap![
-                "1".to_string() => vec!["a".to_string(), "b".to_string(), "c".to_string()],
-                "2".to_string() => vec!["e".to_string()],
+                "1".to_string() => vec!["a".into(), "b".into(), "c".into()],
+                "2".to_string() => vec!["e".into()],
             ]
         );
         assert_eq!(sg.existing_groups.len(), 2);
@@ -215,4 +586,229 @@ mod test {
                 .collect_vec()
         );
     }
+
+    #[test]
+    fn test_coverage() {
+        let mut sg = SecurityGroups::default();
+        assert!(!sg.is_region_complete("1", "eu-west-1"));
+
+        let mut sg1 = SecurityGroups::default();
+        sg1.record_coverage("1", "eu-west-1", "ec2", Coverage::Complete);
+        sg1.record_coverage("1", "eu-west-1", "alb", Coverage::Complete);
+        let mut sg2 = SecurityGroups::default();
+        sg2.record_coverage("1", "us-east-1", "ec2", Coverage::Complete);
+        sg2.record_coverage(
+            "1",
+            "us-east-1",
+            "rds",
+            Coverage::Failed("denied".to_string()),
+        );
+        sg2.record_coverage("2", "eu-west-1", "ec2", Coverage::Complete);
+
+        sg.merge(&sg1);
+        sg.merge(&sg2);
+        assert!(sg.is_region_complete("1", "eu-west-1"));
+        assert!(!sg.is_region_complete("1", "us-east-1"));
+        assert!(sg.is_region_complete("2", "eu-west-1"));
+        assert!(!sg.is_region_complete("2", "us-east-1"));
+        assert_eq!(sg.coverage_gaps("1", "us-east-1"), vec![("rds", "denied")]);
+        assert!(sg.coverage_gaps("1", "eu-west-1").is_empty());
+    }
+
+    #[test]
+    fn test_resolve_names() {
+        let group =
+            |region: &str, group_id: &str, vpc_id: Option<&str>, group_name: &str| ExistingGroup {
+                region: region.to_string(),
+                group_id: group_id.to_string(),
+                vpc_id: vpc_id.map(ToOwned::to_owned),
+                group_name: group_name.to_string(),
+                ..ExistingGroup::default()
+            };
+        let reference = |region: &str, vpc_id: Option<&str>, group_name: &str| NameReference {
+            account_id: String::new(),
+            region: region.to_string(),
+            vpc_id: vpc_id.map(ToOwned::to_owned),
+            group_name: group_name.to_string(),
+            source: "rds@eu-west-1".to_string(),
+            resources: vec![],
+        };
+        let mut sg = SecurityGroups {
+            existing_groups: vec![
+                group("eu-west-1", "sg-1", Some("vpc-1"), "db"),
+                group("eu-west-1", "sg-2", Some("vpc-2"), "db"),
+                group("eu-west-1", "sg-3", None, "cache"),
+                group("eu-west-1", "sg-4", Some("vpc-1"), "cache"),
+                group("us-east-1", "sg-5", Some("vpc-3"), "web"),
+            ],
+            name_references: vec![
+                reference("eu-west-1", Some("vpc-2"), "db"),
+                reference("eu-west-1", None, "db"),
+                reference("eu-west-1", None, "cache"),
+                reference("eu-west-1", None, "web"),
+            ],
+            ..SecurityGroups::default()
+        };
+        sg.resolve_names();
+
+        assert!(sg.name_references.is_empty());
+        assert_eq!(
+            sg.external_references,
+            hashmap![
+                "sg-1".to_string() => vec!["rds@eu-west-1".into()],
+                "sg-2".to_string() => vec!["rds@eu-west-1".into()],
+                "sg-3".to_string() => vec!["rds@eu-west-1".into()],
+            ]
+        );
+        assert_eq!(
+            sg.dangling_references,
+            vec![reference("eu-west-1", None, "web")]
+        );
+    }
+
+    #[test]
+    fn test_resolve_ambiguous_names() {
+        let group = |group_id: &str, vpc_id: &str| ExistingGroup {
+            region: "eu-west-1".to_string(),
+            group_id: group_id.to_string(),
+            vpc_id: Some(vpc_id.to_string()),
+            group_name: "db".to_string(),
+            ..ExistingGroup::default()
+        };
+        let mut sg = SecurityGroups {
+            existing_groups: vec![group("sg-1", "vpc-1"), group("sg-2", "vpc-2")],
+            name_references: vec![NameReference {
+                region: "eu-west-1".to_string(),
+                group_name: "db".to_string(),
+                source: "rds@eu-west-1".to_string(),
+                resources: vec!["DB instance db-1".to_string()],
+                ..NameReference::default()
+            }],
+            ..SecurityGroups::default()
+        };
+        sg.resolve_names();
+
+        assert!(sg.dangling_references.is_empty());
+        assert!(sg.find_unused().is_empty());
+        assert_eq!(sg.attachments["sg-1"], sg.attachments["sg-2"]);
+    }
+
+    #[test]
+    fn test_set_account() {
+        let group = |group_id: &str| ExistingGroup {
+            region: "eu-west-1".to_string(),
+            group_id: group_id.to_string(),
+            group_name: "db".to_string(),
+            ..ExistingGroup::default()
+        };
+        let reference = NameReference {
+            region: "eu-west-1".to_string(),
+            group_name: "db".to_string(),
+            source: "rds@eu-west-1".to_string(),
+            ..NameReference::default()
+        };
+        let mut sg1 = SecurityGroups {
+            existing_groups: vec![group("sg-1")],
+            name_references: vec![reference.clone()],
+            ..SecurityGroups::default()
+        };
+        sg1.set_account("1");
+        let mut sg2 = SecurityGroups {
+            existing_groups: vec![group("sg-2")],
+            ..SecurityGroups::create_from_group_ids(
+                "ec2@eu-west-1".to_string(),
+                ["sg-2".to_string()].into_iter(),
+            )
+        };
+        sg2.set_account("2");
+
+        let mut sg = SecurityGroups::default();
+        sg.merge(&sg1);
+        sg.merge(&sg2);
+        sg.resolve_names();
+        assert_eq!(sg.existing_groups[1].account_id, "2");
+        assert_eq!(
+            sg.external_references,
+            hashmap![
+                "sg-1".to_string() => vec![ServiceReference {
+                    account_id: "1".to_string(),
+                    source: "rds@eu-west-1".to_string(),
+                }],
+                "sg-2".to_string() => vec![ServiceReference {
+                    account_id: "2".to_string(),
+                    source: "ec2@eu-west-1".to_string(),
+                }],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_find_unused_skips_default_groups() {
+        let sg = SecurityGroups {
+            existing_groups: vec![
+                ExistingGroup {
+                    group_id: "sg-1".to_string(),
+                    vpc_id: Some("vpc-1".to_string()),
+                    group_name: "default".to_string(),
+                    is_default: true,
+                    ..ExistingGroup::default()
+                },
+                ExistingGroup {
+                    group_id: "sg-2".to_string(),
+                    vpc_id: Some("vpc-1".to_string()),
+                    group_name: "web".to_string(),
+                    ..ExistingGroup::default()
+                },
+            ],
+            ..SecurityGroups::default()
+        };
+        assert_eq!(
+            sg.find_unused()
+                .into_iter()
+                .map(|group| group.group_id.as_str())
+                .collect_vec(),
+            vec!["sg-2"]
+        );
+    }
+
+    #[test]
+    fn test_find_unused_skips_protected_groups() {
+        let group = |group_id: &str| ExistingGroup {
+            group_id: group_id.to_string(),
+            group_name: group_id.to_string(),
+            ..ExistingGroup::default()
+        };
+        let mut sg = SecurityGroups {
+            existing_groups: vec![group("sg-1"), group("baseline-sg-2")],
+            ..SecurityGroups::default()
+        };
+        sg.protection =
+            Protection::new([], &["^baseline-".to_string()], &[], [], Default::default()).unwrap();
+        assert_eq!(
+            sg.find_unused()
+                .into_iter()
+                .map(|group| group.group_id.as_str())
+                .collect_vec(),
+            vec!["sg-1"]
+        );
+        assert_eq!(
+            sg.protection_reason(&sg.existing_groups[1]).as_deref(),
+            Some("name matches ^baseline-")
+        );
+    }
+
+    #[test]
+    fn test_rule_ports() {
+        let rule = |protocol: &str, from_port, to_port| Rule {
+            protocol: protocol.to_string(),
+            from_port,
+            to_port,
+            ..Rule::default()
+        };
+        assert_eq!(rule("-1", None, None).ports(), "all");
+        assert_eq!(rule("-1", None, None).protocol_name(), "all");
+        assert_eq!(rule("tcp", Some(22), Some(22)).ports(), "22");
+        assert_eq!(rule("tcp", Some(1024), Some(2048)).ports(), "1024-2048");
+        assert_eq!(rule("icmp", Some(-1), Some(-1)).ports(), "all");
+    }
 }
@@ -0,0 +1,45 @@
+use std::time::SystemTime;
+
+use anyhow::Context;
+use aws_sigv4::http_request::{sign, SignableRequest, SigningParams, SigningSettings};
+use aws_types::credentials::ProvideCredentials;
+use http::{Request, StatusCode};
+use hyper::{body, Body};
+
+use crate::client::ClientConfig;
+
+/// Signs `request` for `service` with the credentials of `config` and sends it, for the
+/// few calls to global services whose SDK crates this crate does not depend on.
+pub(crate) async fn send(
+    config: &ClientConfig,
+    service: &str,
+    signing_region: &str,
+    mut request: Request<String>,
+) -> anyhow::Result<(StatusCode, String)> {
+    let credentials = config
+        .sdk_config()
+        .credentials_provider()
+        .context("no credentials configured")?
+        .provide_credentials()
+        .await?;
+
+    let mut params = SigningParams::builder()
+        .access_key(credentials.access_key_id())
+        .secret_key(credentials.secret_access_key())
+        .region(signing_region)
+        .service_name(service)
+        .time(SystemTime::now())
+        .settings(SigningSettings::default());
+    params.set_security_token(credentials.session_token());
+    let params = params.build()?;
+    let (instructions, _) = sign(SignableRequest::from(&request), &params)
+        .map_err(|err| anyhow::anyhow!(err))?
+        .into_parts();
+    instructions.apply_to_request(&mut request);
+
+    let client = hyper::Client::builder().build(hyper_rustls::HttpsConnector::with_native_roots());
+    let response = client.request(request.map(Body::from)).await?;
+    let status = response.status();
+    let text = String::from_utf8(body::to_bytes(response.into_body()).await?.to_vec())?;
+    Ok((status, text))
+}
@@ -0,0 +1,157 @@
+use std::{collections::HashMap, future::Future, time::Duration};
+
+use aws_config::RetryConfig;
+use parking_lot::Mutex;
+use tokio::{sync::Semaphore, time::Instant};
+
+const MAX_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Debug)]
+pub struct ThrottleConfig {
+    /// Maximum number of provider scans running at the same time across all regions.
+    pub max_concurrency: usize,
+    /// Steady rate of requests, pages of a listing included, allowed for a single
+    /// service in a single region.
+    pub requests_per_second: f64,
+    /// Retries of a throttled request, made by the SDK with jittered exponential backoff.
+    pub max_retries: u32,
+}
+
+impl Default for ThrottleConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrency: 16,
+            requests_per_second: 10.0,
+            max_retries: 5,
+        }
+    }
+}
+
+struct RateLimiter {
+    min_interval: Duration,
+    interval: Duration,
+    next: Instant,
+}
+
+impl RateLimiter {
+    fn new(interval: Duration) -> Self {
+        Self {
+            min_interval: interval,
+            interval,
+            next: Instant::now(),
+        }
+    }
+
+    fn reserve(&mut self) -> Instant {
+        let slot = self.next.max(Instant::now());
+        self.next = slot + self.interval;
+        slot
+    }
+
+    fn slow_down(&mut self) {
+        self.interval = (self.interval * 2).min(MAX_INTERVAL);
+    }
+
+    fn speed_up(&mut self) {
+        self.interval = self.interval.mul_f64(0.9).max(self.min_interval);
+    }
+}
+
+/// Shared limits for every AWS call made during a run: a global concurrency cap on
+/// provider scans, and an adaptive rate limiter per service and region for requests.
+pub struct Throttle {
+    config: ThrottleConfig,
+    permits: Semaphore,
+    limiters: Mutex<HashMap<(String, String), RateLimiter>>,
+}
+
+impl Throttle {
+    pub fn new(config: ThrottleConfig) -> Self {
+        Self {
+            permits: Semaphore::new(config.max_concurrency.max(1)),
+            limiters: Mutex::new(HashMap::new()),
+            config,
+        }
+    }
+
+    pub fn retry_config(&self) -> RetryConfig {
+        RetryConfig::new().with_max_attempts(self.config.max_retries + 1)
+    }
+
+    /// Waits for the next request slot of `service` in `region`.
+    pub async fn pace(&self, service: &str, region: &str) {
+        let slot = self.with_limiter(service, region, RateLimiter::reserve);
+        tokio::time::sleep_until(slot).await;
+    }
+
+    /// Halves the request rate of `service` in `region`, after a request was throttled
+    /// despite the SDK retries.
+    pub fn slow_down(&self, service: &str, region: &str) {
+        self.with_limiter(service, region, RateLimiter::slow_down);
+    }
+
+    /// Recovers the request rate of `service` in `region` after a successful request.
+    pub fn speed_up(&self, service: &str, region: &str) {
+        self.with_limiter(service, region, RateLimiter::speed_up);
+    }
+
+    /// Runs one provider scan once fewer than `max_concurrency` are running.
+    pub async fn run<T>(&self, f: impl Future<Output = T>) -> T {
+        let _permit = self
+            .permits
+            .acquire()
+            .await
+            .expect("semaphore is never closed");
+        f.await
+    }
+
+    fn with_limiter<R>(
+        &self,
+        service: &str,
+        region: &str,
+        f: impl FnOnce(&mut RateLimiter) -> R,
+    ) -> R {
+        let mut limiters = self.limiters.lock();
+        let limiter = limiters
+            .entry((service.to_owned(), region.to_owned()))
+            .or_insert_with(|| {
+                let interval = Duration::try_from_secs_f64(1.0 / self.config.requests_per_second)
+                    .map_or(MAX_INTERVAL, |interval| interval.min(MAX_INTERVAL));
+                RateLimiter::new(interval)
+            });
+        f(limiter)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use tokio::time::Instant;
+
+    use super::{RateLimiter, MAX_INTERVAL};
+
+    #[test]
+    fn test_rate_limiter_spacing() {
+        let mut limiter = RateLimiter::new(Duration::from_millis(100));
+        let first = limiter.reserve();
+        let second = limiter.reserve();
+        assert_eq!(second - first, Duration::from_millis(100));
+        assert!(first <= Instant::now());
+    }
+
+    #[test]
+    fn test_rate_limiter_adapts() {
+        let mut limiter = RateLimiter::new(Duration::from_millis(100));
+        limiter.slow_down();
+        assert_eq!(limiter.interval, Duration::from_millis(200));
+        for _ in 0..100 {
+            limiter.slow_down();
+        }
+        assert_eq!(limiter.interval, MAX_INTERVAL);
+        for _ in 0..1000 {
+            limiter.speed_up();
+        }
+        assert_eq!(limiter.interval, Duration::from_millis(100));
+    }
+}
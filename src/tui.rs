@@ -0,0 +1,713 @@
+//! Terminal browser over an inventory: regions, VPCs and groups on the left, the
+//! selected group on the right, and links along the references between groups.
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::io::{self, Stdout};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use crossterm::execute;
+use crossterm::terminal::{
+    disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
+};
+use itertools::Itertools;
+use ratatui::backend::CrosstermBackend;
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::{Frame, Terminal};
+
+use crate::graph::ReferenceGraph;
+use crate::plan::Plan;
+use crate::security::{ExistingGroup, Rule, SecurityGroups};
+
+/// A row of the tree on the left.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Row<'a> {
+    Region {
+        account_id: &'a str,
+        region: &'a str,
+    },
+    Vpc {
+        key: NodeKey<'a>,
+    },
+    Group(&'a ExistingGroup),
+}
+
+/// A region, or a VPC of a region when `vpc_id` is set. EC2-Classic groups have no VPC.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeKey<'a> {
+    pub account_id: &'a str,
+    pub region: &'a str,
+    pub vpc_id: Option<Option<&'a str>>,
+}
+
+/// A group the selected group links to through its rules.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Link<'a> {
+    pub group_id: &'a str,
+    /// Whether the selected group references the linked group, rather than the other way.
+    pub outgoing: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Focus {
+    Tree,
+    Links,
+}
+
+/// Browser state, separate from drawing so it can be driven without a terminal.
+pub struct App<'a> {
+    groups: &'a SecurityGroups,
+    graph: ReferenceGraph<'a>,
+    unused: HashSet<&'a str>,
+    referencing: HashMap<&'a str, Vec<&'a str>>,
+    collapsed: HashSet<NodeKey<'a>>,
+    only_unused: bool,
+    rows: Vec<Row<'a>>,
+    tree: ListState,
+    links: ListState,
+    focus: Focus,
+    history: Vec<&'a str>,
+    marked: BTreeSet<&'a str>,
+    status: String,
+}
+
+impl<'a> App<'a> {
+    pub fn new(groups: &'a SecurityGroups) -> Self {
+        let mut referencing: HashMap<&str, Vec<&str>> = HashMap::new();
+        for group in groups.existing_groups.iter() {
+            for target in group.references.iter() {
+                referencing.entry(target).or_default().push(&group.group_id);
+            }
+        }
+        for sources in referencing.values_mut() {
+            sources.sort_unstable();
+        }
+        let mut app = Self {
+            groups,
+            graph: groups.reference_graph(),
+            unused: groups
+                .find_unused()
+                .into_iter()
+                .map(|x| x.group_id.as_str())
+                .collect(),
+            referencing,
+            collapsed: HashSet::new(),
+            only_unused: false,
+            rows: vec![],
+            tree: ListState::default(),
+            links: ListState::default(),
+            focus: Focus::Tree,
+            history: vec![],
+            marked: BTreeSet::new(),
+            status: String::new(),
+        };
+        app.rebuild();
+        app.tree.select((!app.rows.is_empty()).then_some(0));
+        app
+    }
+
+    pub fn rows(&self) -> &[Row<'a>] {
+        &self.rows
+    }
+
+    pub fn selected_group(&self) -> Option<&'a ExistingGroup> {
+        match self.tree.selected().and_then(|i| self.rows.get(i)) {
+            Some(Row::Group(group)) => Some(group),
+            _ => None,
+        }
+    }
+
+    pub fn marked(&self) -> impl Iterator<Item = &'a ExistingGroup> + '_ {
+        self.groups
+            .existing_groups
+            .iter()
+            .filter(|x| self.marked.contains(x.group_id.as_str()))
+    }
+
+    pub fn status(&self) -> &str {
+        &self.status
+    }
+
+    fn is_visible(&self, group: &ExistingGroup) -> bool {
+        !self.only_unused || self.unused.contains(group.group_id.as_str())
+    }
+
+    /// Rebuilds the tree rows after a filter or collapse change.
+    fn rebuild(&mut self) {
+        let mut rows = vec![];
+        let by_region = self
+            .groups
+            .existing_groups
+            .iter()
+            .filter(|x| self.is_visible(x))
+            .sorted_by_key(|x| (&x.account_id, &x.region, &x.vpc_id, &x.group_id))
+            .group_by(|x| (x.account_id.as_str(), x.region.as_str()));
+        for ((account_id, region), groups) in by_region.into_iter() {
+            rows.push(Row::Region { account_id, region });
+            let region_key = NodeKey {
+                account_id,
+                region,
+                vpc_id: None,
+            };
+            if self.collapsed.contains(&region_key) {
+                continue;
+            }
+            for (vpc_id, groups) in groups.group_by(|x| x.vpc_id.as_deref()).into_iter() {
+                let key = NodeKey {
+                    vpc_id: Some(vpc_id),
+                    ..region_key
+                };
+                rows.push(Row::Vpc { key });
+                if !self.collapsed.contains(&key) {
+                    rows.extend(groups.map(Row::Group));
+                }
+            }
+        }
+        self.rows = rows;
+    }
+
+    /// Keeps the selection on the same row across a rebuild, or on the closest one.
+    fn rebuild_keeping(&mut self, row: Option<Row<'a>>) {
+        let index = self.tree.selected().unwrap_or(0);
+        self.rebuild();
+        let index = row
+            .and_then(|row| self.rows.iter().position(|x| *x == row))
+            .unwrap_or_else(|| index.min(self.rows.len().saturating_sub(1)));
+        self.tree.select((!self.rows.is_empty()).then_some(index));
+        self.links.select(None);
+    }
+
+    fn selected_row(&self) -> Option<Row<'a>> {
+        self.tree.selected().and_then(|i| self.rows.get(i)).copied()
+    }
+
+    /// Groups the selected group references, then the groups referencing it.
+    pub fn links(&self) -> Vec<Link<'a>> {
+        let Some(group) = self.selected_group() else {
+            return vec![];
+        };
+        let outgoing = group.references.iter().sorted().map(|group_id| Link {
+            group_id,
+            outgoing: true,
+        });
+        let incoming = self
+            .referencing
+            .get(group.group_id.as_str())
+            .into_iter()
+            .flatten()
+            .map(|group_id| Link {
+                group_id,
+                outgoing: false,
+            });
+        outgoing.chain(incoming).collect()
+    }
+
+    pub fn toggle_unused(&mut self) {
+        self.only_unused = !self.only_unused;
+        let row = self.selected_row();
+        self.rebuild_keeping(row);
+        self.status = match self.only_unused {
+            true => "showing unused groups only".to_string(),
+            false => "showing all groups".to_string(),
+        };
+    }
+
+    /// Collapses or expands the selected region or VPC.
+    pub fn toggle_collapsed(&mut self) {
+        let key = match self.selected_row() {
+            Some(Row::Region { account_id, region }) => NodeKey {
+                account_id,
+                region,
+                vpc_id: None,
+            },
+            Some(Row::Vpc { key }) => key,
+            _ => return,
+        };
+        if !self.collapsed.remove(&key) {
+            self.collapsed.insert(key);
+        }
+        let row = self.selected_row();
+        self.rebuild_keeping(row);
+    }
+
+    /// Marks or unmarks the selected group for the deletion plan. Only unused groups in
+    /// regions with complete coverage can be marked, as apply refuses anything else.
+    pub fn toggle_mark(&mut self) {
+        let Some(group) = self.selected_group() else {
+            return;
+        };
+        let group_id = group.group_id.as_str();
+        if self.marked.remove(group_id) {
+            self.status = format!("unmarked {}", group_id);
+        } else if group.is_default {
+            self.status = format!("{} is a default group", group_id);
+        } else if let Some(reason) = self.groups.protection_reason(group) {
+            self.status = format!("{} is protected: {}", group_id, reason);
+        } else if !self.unused.contains(group_id) {
+            self.status = format!("{} is used", group_id);
+        } else if !self
+            .groups
+            .is_region_complete(&group.account_id, &group.region)
+        {
+            self.status = format!("coverage of {} is incomplete", group.region);
+        } else {
+            self.marked.insert(group_id);
+            self.status = format!("marked {}, {} in plan", group_id, self.marked.len());
+        }
+    }
+
+    /// Selects `group_id`, expanding its region and VPC and clearing the unused filter
+    /// when it hides the group.
+    pub fn jump_to(&mut self, group_id: &str) -> bool {
+        let Some(group) = self
+            .groups
+            .existing_groups
+            .iter()
+            .find(|x| x.group_id == group_id)
+        else {
+            self.status = format!("{} is not in the inventory", group_id);
+            return false;
+        };
+        if let Some(current) = self.selected_group() {
+            self.history.push(&current.group_id);
+        }
+        if !self.is_visible(group) {
+            self.only_unused = false;
+            self.status = format!("showing all groups to reach {}", group_id);
+        }
+        let region_key = NodeKey {
+            account_id: &group.account_id,
+            region: &group.region,
+            vpc_id: None,
+        };
+        self.collapsed.remove(&region_key);
+        self.collapsed.remove(&NodeKey {
+            vpc_id: Some(group.vpc_id.as_deref()),
+            ..region_key
+        });
+        self.rebuild_keeping(Some(Row::Group(group)));
+        true
+    }
+
+    /// Returns to the group selected before the last jump.
+    pub fn back(&mut self) {
+        if let Some(group_id) = self.history.pop() {
+            self.jump_to(group_id);
+            self.history.pop();
+        }
+    }
+
+    fn follow_link(&mut self) {
+        let links = self.links();
+        if let Some(link) = self.links.selected().and_then(|i| links.get(i)) {
+            if self.jump_to(link.group_id) {
+                self.focus = Focus::Tree;
+            }
+        }
+    }
+
+    fn move_selection(&mut self, delta: isize) {
+        let links = self.links().len();
+        let (state, len) = match self.focus {
+            Focus::Tree => (&mut self.tree, self.rows.len()),
+            Focus::Links => (&mut self.links, links),
+        };
+        if len == 0 {
+            return;
+        }
+        let index = state.selected().map_or(0, |i| {
+            (i as isize + delta).clamp(0, len as isize - 1) as usize
+        });
+        state.select(Some(index));
+        if self.focus == Focus::Tree {
+            self.links.select(None);
+        }
+    }
+
+    fn switch_focus(&mut self) {
+        self.focus = match self.focus {
+            Focus::Tree if !self.links().is_empty() => {
+                self.links.select(Some(0));
+                Focus::Links
+            }
+            _ => {
+                self.links.select(None);
+                Focus::Tree
+            }
+        };
+    }
+
+    /// Handles one key, returning false to quit.
+    fn handle_key(&mut self, code: KeyCode, plan_path: Option<&PathBuf>) -> bool {
+        match code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Up | KeyCode::Char('k') => self.move_selection(-1),
+            KeyCode::Down | KeyCode::Char('j') => self.move_selection(1),
+            KeyCode::PageUp => self.move_selection(-10),
+            KeyCode::PageDown => self.move_selection(10),
+            KeyCode::Tab => self.switch_focus(),
+            KeyCode::Enter if self.focus == Focus::Links => self.follow_link(),
+            KeyCode::Enter | KeyCode::Char(' ') if self.selected_group().is_none() => {
+                self.toggle_collapsed()
+            }
+            KeyCode::Char('m') | KeyCode::Char(' ') => self.toggle_mark(),
+            KeyCode::Char('u') => self.toggle_unused(),
+            KeyCode::Backspace | KeyCode::Char('b') => self.back(),
+            KeyCode::Char('w') => self.write_plan(plan_path),
+            _ => {}
+        }
+        true
+    }
+
+    fn write_plan(&mut self, path: Option<&PathBuf>) {
+        let Some(path) = path else {
+            self.status = "pass --out to write a plan".to_string();
+            return;
+        };
+        let plan = Plan::from_groups(self.groups, self.marked(), false);
+        self.status = match plan.save(path) {
+            Ok(()) => format!("saved {} groups to {}", plan.groups.len(), path.display()),
+            Err(err) => format!("{:#}", err),
+        };
+    }
+}
+
+/// Runs the browser until the user quits. `plan_path` is where `w` writes the marked
+/// groups as a [`Plan`].
+pub fn run(groups: &SecurityGroups, plan_path: Option<PathBuf>) -> anyhow::Result<()> {
+    enable_raw_mode()?;
+    let mut stdout = io::stdout();
+    execute!(stdout, EnterAlternateScreen)?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(stdout))?;
+    let result = event_loop(&mut terminal, App::new(groups), plan_path.as_ref());
+    disable_raw_mode()?;
+    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+    terminal.show_cursor()?;
+    result
+}
+
+fn event_loop(
+    terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+    mut app: App<'_>,
+    plan_path: Option<&PathBuf>,
+) -> anyhow::Result<()> {
+    loop {
+        terminal.draw(|frame| draw(frame, &mut app))?;
+        if !event::poll(Duration::from_millis(250))? {
+            continue;
+        }
+        if let Event::Key(key) = event::read()? {
+            if key.kind == KeyEventKind::Press && !app.handle_key(key.code, plan_path) {
+                return Ok(());
+            }
+        }
+    }
+}
+
+const HELP: &str =
+    "↑↓ move  tab links  enter follow/collapse  b back  u unused  m mark  w write plan  q quit";
+
+fn draw(frame: &mut Frame<'_>, app: &mut App<'_>) {
+    let [main, status] =
+        Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(frame.size());
+    let [tree, detail] =
+        Layout::horizontal([Constraint::Percentage(40), Constraint::Percentage(60)]).areas(main);
+
+    draw_tree(frame, app, tree);
+    draw_detail(frame, app, detail);
+
+    let status_text = match app.status.is_empty() {
+        true => HELP.to_string(),
+        false => format!("{}  |  {}", app.status, HELP),
+    };
+    frame.render_widget(
+        Paragraph::new(status_text).style(Style::default().add_modifier(Modifier::REVERSED)),
+        status,
+    );
+}
+
+fn border(title: String, focused: bool) -> Block<'static> {
+    let style = match focused {
+        true => Style::default().fg(Color::Cyan),
+        false => Style::default(),
+    };
+    Block::default()
+        .borders(Borders::ALL)
+        .border_style(style)
+        .title(title)
+}
+
+fn draw_tree(frame: &mut Frame<'_>, app: &mut App<'_>, area: Rect) {
+    let items = app
+        .rows
+        .iter()
+        .map(|row| match row {
+            Row::Region { account_id, region } => {
+                let collapsed = app.collapsed.contains(&NodeKey {
+                    account_id,
+                    region,
+                    vpc_id: None,
+                });
+                let mut line = format!("{} {}/{}", arrow(collapsed), account_id, region);
+                if !app.groups.is_region_complete(account_id, region) {
+                    line.push_str(" (incomplete)");
+                }
+                ListItem::new(line).style(Style::default().add_modifier(Modifier::BOLD))
+            }
+            Row::Vpc { key } => {
+                let collapsed = app.collapsed.contains(key);
+                let vpc_id = key.vpc_id.flatten().unwrap_or("EC2-Classic");
+                ListItem::new(format!("  {} {}", arrow(collapsed), vpc_id))
+            }
+            Row::Group(group) => {
+                let mark = match app.marked.contains(group.group_id.as_str()) {
+                    true => "[x]",
+                    false => "[ ]",
+                };
+                let item = ListItem::new(format!(
+                    "      {} {} {}",
+                    mark, group.group_id, group.group_name
+                ));
+                match app.unused.contains(group.group_id.as_str()) {
+                    true => item.style(Style::default().fg(Color::Yellow)),
+                    false => item,
+                }
+            }
+        })
+        .collect_vec();
+    let title = match app.only_unused {
+        true => format!("Unused groups ({} marked)", app.marked.len()),
+        false => format!("Groups ({} marked)", app.marked.len()),
+    };
+    let list = List::new(items)
+        .block(border(title, app.focus == Focus::Tree))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    frame.render_stateful_widget(list, area, &mut app.tree);
+}
+
+fn arrow(collapsed: bool) -> &'static str {
+    match collapsed {
+        true => "▸",
+        false => "▾",
+    }
+}
+
+fn rule_line(rule: &Rule) -> Line<'static> {
+    let mut line = format!(
+        "  {:<5} {:<11} {}",
+        rule.protocol_name(),
+        rule.ports(),
+        rule.peer
+    );
+    if let Some(description) = &rule.description {
+        line.push_str(&format!("  {}", description));
+    }
+    Line::from(line)
+}
+
+fn draw_detail(frame: &mut Frame<'_>, app: &mut App<'_>, area: Rect) {
+    let Some(group) = app.selected_group() else {
+        frame.render_widget(
+            Paragraph::new("Select a group").block(border("Details".to_string(), false)),
+            area,
+        );
+        return;
+    };
+    let links = app.links();
+    let [info, links_area] = Layout::vertical([
+        Constraint::Min(1),
+        Constraint::Length(links.len().clamp(1, 10) as u16 + 2),
+    ])
+    .areas(area);
+
+    let heading = |text: &str| {
+        Line::styled(
+            text.to_string(),
+            Style::default().add_modifier(Modifier::BOLD),
+        )
+    };
+    let state = if group.is_default {
+        "default".to_string()
+    } else if app.graph.is_used(&group.group_id) {
+        "used".to_string()
+    } else if let Some(reason) = app.groups.protection_reason(group) {
+        format!("protected, {}", reason)
+    } else {
+        "unused".to_string()
+    };
+    let mut lines = vec![
+        Line::from(format!("{} ({})", group.group_name, state)),
+        Line::from(group.group_description.clone()),
+        Line::from(format!(
+            "{}/{} {}",
+            group.account_id,
+            group.region,
+            group.vpc_id.as_deref().unwrap_or("EC2-Classic")
+        )),
+    ];
+    for (provider, reason) in app.groups.coverage_gaps(&group.account_id, &group.region) {
+        lines.push(Line::styled(
+            format!("{} failed in this region: {}", provider, reason),
+            Style::default().fg(Color::Red),
+        ));
+    }
+
+    lines.push(Line::default());
+    lines.push(heading("Services"));
+    let attachments = app.groups.attachments.get(&group.group_id);
+    for service in app
+        .graph
+        .referencing_services(&group.group_id)
+        .into_iter()
+        .sorted()
+    {
+        let resources = attachments
+            .into_iter()
+            .flatten()
+            .filter(|x| x.reference == *service)
+            .map(|x| x.resource.as_str())
+            .join(", ");
+        lines.push(Line::from(match resources.is_empty() {
+            true => format!("  {}", service),
+            false => format!("  {}: {}", service, resources),
+        }));
+    }
+
+    lines.push(Line::default());
+    lines.push(heading("Tags"));
+    for (key, value) in group.tags.iter() {
+        lines.push(Line::from(format!("  {} = {}", key, value)));
+    }
+
+    lines.push(Line::default());
+    lines.push(heading("Ingress"));
+    lines.extend(group.ingress.iter().map(rule_line));
+    lines.push(Line::default());
+    lines.push(heading("Egress"));
+    lines.extend(group.egress.iter().map(rule_line));
+
+    frame.render_widget(
+        Paragraph::new(lines)
+            .block(border(group.group_id.clone(), false))
+            .wrap(Wrap { trim: false }),
+        info,
+    );
+
+    let items = links
+        .iter()
+        .map(|link| {
+            let direction = match link.outgoing {
+                true => "allows",
+                false => "allowed by",
+            };
+            let name = app
+                .groups
+                .existing_groups
+                .iter()
+                .find(|x| x.group_id == link.group_id)
+                .map_or("not in inventory", |x| x.group_name.as_str());
+            ListItem::new(format!("{} {} {}", direction, link.group_id, name))
+        })
+        .collect_vec();
+    let list = List::new(items)
+        .block(border("References".to_string(), app.focus == Focus::Links))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    frame.render_stateful_widget(list, links_area, &mut app.links);
+}
+
+#[cfg(test)]
+mod test {
+    use maplit::hashmap;
+
+    use super::{App, Row};
+    use crate::security::{Coverage, ExistingGroup, SecurityGroups};
+
+    fn groups() -> SecurityGroups {
+        let group = |group_id: &str, vpc_id: &str, references: &[&str]| ExistingGroup {
+            account_id: "1".to_string(),
+            region: "eu-west-1".to_string(),
+            group_id: group_id.to_string(),
+            group_name: group_id.to_string(),
+            vpc_id: Some(vpc_id.to_string()),
+            references: references.iter().map(|x| x.to_string()).collect(),
+            ..ExistingGroup::default()
+        };
+        let mut groups = SecurityGroups {
+            existing_groups: vec![
+                group("sg-1", "vpc-1", &["sg-2"]),
+                group("sg-2", "vpc-1", &[]),
+                group("sg-3", "vpc-2", &[]),
+            ],
+            external_references: hashmap![
+                "sg-1".to_string() => vec!["ec2@eu-west-1".into()],
+            ],
+            ..SecurityGroups::default()
+        };
+        groups.record_coverage("1", "eu-west-1", "ec2", Coverage::Complete);
+        groups
+    }
+
+    fn group_ids<'a>(app: &App<'a>) -> Vec<&'a str> {
+        app.rows()
+            .iter()
+            .filter_map(|row| match row {
+                Row::Group(group) => Some(group.group_id.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_filter_and_jump() {
+        let groups = groups();
+        let mut app = App::new(&groups);
+        assert_eq!(app.rows().len(), 6);
+        assert_eq!(group_ids(&app), ["sg-1", "sg-2", "sg-3"]);
+
+        app.toggle_unused();
+        assert_eq!(group_ids(&app), ["sg-3"]);
+
+        // sg-1 is used, so jumping to it clears the filter.
+        assert!(app.jump_to("sg-1"));
+        assert_eq!(group_ids(&app), ["sg-1", "sg-2", "sg-3"]);
+        assert_eq!(app.selected_group().unwrap().group_id, "sg-1");
+        let links = app.links();
+        assert_eq!(links.len(), 1);
+        assert!(links[0].outgoing);
+
+        assert!(app.jump_to(links[0].group_id));
+        assert_eq!(app.selected_group().unwrap().group_id, "sg-2");
+        assert_eq!(app.links()[0].group_id, "sg-1");
+        app.back();
+        assert_eq!(app.selected_group().unwrap().group_id, "sg-1");
+        assert!(!app.jump_to("sg-9"));
+    }
+
+    #[test]
+    fn test_collapse_and_mark() {
+        let groups = groups();
+        let mut app = App::new(&groups);
+        app.toggle_collapsed();
+        assert_eq!(app.rows().len(), 1);
+        app.toggle_collapsed();
+        assert_eq!(app.rows().len(), 6);
+
+        app.jump_to("sg-1");
+        app.toggle_mark();
+        assert_eq!(app.status(), "sg-1 is used");
+        app.jump_to("sg-3");
+        app.toggle_mark();
+        assert_eq!(
+            app.marked()
+                .map(|x| x.group_id.as_str())
+                .collect::<Vec<_>>(),
+            ["sg-3"]
+        );
+        app.toggle_mark();
+        assert_eq!(app.marked().count(), 0);
+    }
+}
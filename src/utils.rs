@@ -1,21 +1,13 @@
-use aws_config::meta::region::RegionProviderChain;
-use itertools::Itertools;
-use log::info;
+use aws_config::RetryConfig;
 
-pub async fn load_regions() -> anyhow::Result<impl Iterator<Item = aws_sdk_ec2::Region>> {
-    info!("loading regions");
-    let config = aws_config::from_env()
-        .region(RegionProviderChain::default_provider())
-        .load()
-        .await;
-    let client = aws_sdk_ec2::Client::new(&config);
-    let response = client.describe_regions().send().await?;
+use crate::client::{ClientConfig, ClientOptions};
 
-    let res = Vec::from(response.regions().unwrap())
-        .into_iter()
-        .map(|x| aws_sdk_ec2::Region::new(x.region_name.unwrap()))
-        .collect_vec()
-        .into_iter();
-
-    Ok(res)
+pub(crate) async fn load_account_id(options: &ClientOptions) -> anyhow::Result<String> {
+    let config = ClientConfig::load(options.default_region(), options, RetryConfig::new()).await;
+    let client = config.sts();
+    let response = client.get_caller_identity().send().await?;
+    response
+        .account()
+        .map(ToOwned::to_owned)
+        .ok_or_else(|| anyhow::anyhow!("caller identity has no account"))
 }
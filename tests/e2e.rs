@@ -0,0 +1,62 @@
+//! End-to-end run of the binary against a local AWS emulator such as LocalStack or moto.
+//!
+//! Start the emulator and run
+//! `AWS_SG_CLEANUP_E2E_ENDPOINT=http://localhost:4566 cargo test --test e2e -- --ignored`.
+
+use std::process::{Command, Output};
+
+const ENDPOINT_VAR: &str = "AWS_SG_CLEANUP_E2E_ENDPOINT";
+
+fn run(endpoint: &str, args: &[&str]) -> Output {
+    let output = Command::new(env!("CARGO_BIN_EXE_aws-sg-cleanup"))
+        .arg("--endpoint-url")
+        .arg(endpoint)
+        .args(args)
+        .env("AWS_ACCESS_KEY_ID", "test")
+        .env("AWS_SECRET_ACCESS_KEY", "test")
+        .env("AWS_REGION", "us-east-1")
+        .env_remove("AWS_PROFILE")
+        .output()
+        .expect("failed to run aws-sg-cleanup");
+    assert!(
+        output.status.success(),
+        "{:?} failed: {}",
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    output
+}
+
+fn noise_count(endpoint: &str) -> usize {
+    let output = run(endpoint, &["print"]);
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|line| line.contains("noise-"))
+        .count()
+}
+
+#[test]
+#[ignore]
+fn test_make_noise_print_clean() {
+    let Ok(endpoint) = std::env::var(ENDPOINT_VAR) else {
+        panic!("{} is not set", ENDPOINT_VAR);
+    };
+
+    run(&endpoint, &["make-noise"]);
+    assert!(noise_count(&endpoint) >= 20);
+
+    let inventory =
+        std::env::temp_dir().join(format!("aws-sg-cleanup-e2e-{}.json", std::process::id()));
+    let inventory = inventory.to_str().unwrap();
+    run(&endpoint, &["scan", "--output", inventory]);
+    let output = run(&endpoint, &["print", "--from", inventory]);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("noise-"));
+    std::fs::remove_file(inventory).unwrap();
+
+    // Emulators rarely implement every provider, so coverage is usually incomplete.
+    run(
+        &endpoint,
+        &["clean", "--yes", "--allow-incomplete-coverage"],
+    );
+    assert_eq!(noise_count(&endpoint), 0);
+}
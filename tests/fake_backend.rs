@@ -0,0 +1,214 @@
+#![cfg(all(
+    feature = "alb",
+    feature = "ec2",
+    feature = "elasticache",
+    feature = "lambda",
+    feature = "rds"
+))]
+
+use std::{collections::HashSet, path::Path};
+
+use aws_sg_cleanup::{
+    backend::Backend,
+    cleanup,
+    error::ProviderError,
+    explain::reference_paths,
+    fake::{FakeBackend, Fixture},
+    graph::deletion_order,
+    plan::Plan,
+    scan::{scan_region, ScanReport},
+    throttle::{Throttle, ThrottleConfig},
+    Registry,
+};
+use itertools::Itertools;
+
+const ACCOUNT: &str = "123456789012";
+
+fn fixture() -> Fixture {
+    Fixture::load(&Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/account.json"))
+        .unwrap()
+}
+
+async fn scan(backends: &[&FakeBackend], throttle: &Throttle) -> ScanReport {
+    let providers = Registry::builtin().select::<&str>(&[], &[]).unwrap();
+    let mut report = ScanReport::default();
+    for backend in backends {
+        report.merge(scan_region(ACCOUNT, *backend, &providers, throttle, None).await);
+    }
+    report.groups.resolve_names();
+    report
+}
+
+fn group_ids<'a>(ids: impl IntoIterator<Item = &'a str>) -> HashSet<&'a str> {
+    ids.into_iter().collect()
+}
+
+#[tokio::test]
+async fn test_find_unused() {
+    let backends = fixture().backends();
+    let throttle = Throttle::new(ThrottleConfig::default());
+    let report = scan(&backends.values().collect_vec(), &throttle).await;
+
+    let unused = report.groups.find_unused();
+    assert_eq!(
+        group_ids(unused.iter().map(|group| group.group_id.as_str())),
+        group_ids([
+            "sg-a",
+            "sg-b",
+            "sg-c",
+            "sg-d",
+            "sg-e",
+            "sg-f",
+            "sg-orphan",
+            "sg-x"
+        ])
+    );
+
+    let dangling = report
+        .groups
+        .dangling_references
+        .iter()
+        .map(|reference| reference.group_name.as_str())
+        .collect_vec();
+    assert_eq!(dangling, ["missing"]);
+
+    assert!(unused.iter().all(|group| group.account_id == ACCOUNT));
+    assert!(report.groups.is_region_complete(ACCOUNT, "eu-west-1"));
+    assert!(!report.groups.is_region_complete(ACCOUNT, "us-east-1"));
+    assert!(matches!(
+        report.failures.as_slice(),
+        [ProviderError::AccessDenied {
+            provider: "lambda",
+            ..
+        }]
+    ));
+}
+
+#[tokio::test]
+async fn test_skipped_provider() {
+    let backends = fixture().backends();
+    let throttle = Throttle::new(ThrottleConfig::default());
+    let providers = Registry::builtin().select(&[], &["rds"]).unwrap();
+    let mut report =
+        scan_region(ACCOUNT, &backends["eu-west-1"], &providers, &throttle, None).await;
+    report.groups.resolve_names();
+
+    assert!(!report.groups.is_region_complete(ACCOUNT, "eu-west-1"));
+    assert_eq!(
+        report.groups.coverage_gaps(ACCOUNT, "eu-west-1"),
+        [("rds", "skipped")]
+    );
+    let plan = Plan::new(&report.groups, false);
+    assert!(plan.groups.is_empty());
+}
+
+#[tokio::test]
+async fn test_why() {
+    let backends = fixture().backends();
+    let throttle = Throttle::new(ThrottleConfig::default());
+    let report = scan(&backends.values().collect_vec(), &throttle).await;
+
+    let paths = reference_paths(&report.groups, "sg-lb", 10)
+        .paths
+        .iter()
+        .map(|path| path.describe("sg-lb"))
+        .collect_vec();
+    assert_eq!(
+        paths,
+        [
+            vec![
+                "sg-lb is attached to network interface eni-123 \
+                 (arn:aws:ecs:eu-west-1:123456789012:attachment/task-x) \
+                 through 123456789012/ec2@eu-west-1"
+            ],
+            vec![
+                "sg-lb is referenced by ingress rule tcp 80 from sg-lb of sg-web (web)",
+                "sg-web is attached to instance i-1 through 123456789012/ec2@eu-west-1",
+            ],
+        ]
+    );
+
+    let paths = reference_paths(&report.groups, "sg-monitor", 10).paths;
+    assert_eq!(
+        paths[0].describe("sg-monitor"),
+        [
+            "sg-monitor is referenced by egress rule tcp 9100 to sg-monitor of sg-web (web)",
+            "sg-web is attached to instance i-1 through 123456789012/ec2@eu-west-1",
+        ]
+    );
+
+    let paths = reference_paths(&report.groups, "sg-db", 10).paths;
+    assert_eq!(
+        paths[0].describe("sg-db"),
+        ["sg-db is attached to DB instance db-1 through 123456789012/rds@eu-west-1"]
+    );
+}
+
+#[tokio::test]
+async fn test_clean() {
+    let backends = fixture().backends();
+    let backend = &backends["eu-west-1"];
+    let throttle = Throttle::new(ThrottleConfig::default());
+    let report = scan(&[backend], &throttle).await;
+
+    let steps = deletion_order(&report.groups.find_unused());
+    cleanup::execute(backend, &steps).await.unwrap();
+
+    let remaining = backend.state().security_groups;
+    assert_eq!(
+        group_ids(remaining.iter().map(|group| group.group_id.as_str())),
+        group_ids(["sg-default", "sg-web", "sg-monitor", "sg-lb", "sg-db"])
+    );
+
+    // The remaining groups are untouched, including rules of groups that stay.
+    let web = backend
+        .describe_security_group("sg-web")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(web.ingress.len(), 2);
+    assert_eq!(web.egress.len(), 1);
+}
+
+#[tokio::test]
+async fn test_clean_describe_failure() {
+    let mut fixture = fixture();
+    let region = fixture.regions.get_mut("eu-west-1").unwrap();
+    region
+        .denied
+        .push("describe_security_groups:sg-a".to_string());
+    let backends = fixture.backends();
+    let backend = &backends["eu-west-1"];
+    let throttle = Throttle::new(ThrottleConfig::default());
+    let report = scan(&[backend], &throttle).await;
+
+    // Revoking the rules of sg-a is skipped, the rest of the plan still runs.
+    let steps = deletion_order(&report.groups.find_unused());
+    cleanup::execute(backend, &steps).await.unwrap();
+
+    let remaining = backend.state().security_groups;
+    let remaining = group_ids(remaining.iter().map(|group| group.group_id.as_str()));
+    for group_id in ["sg-c", "sg-d", "sg-e", "sg-f", "sg-orphan"] {
+        assert!(!remaining.contains(group_id), "{} not deleted", group_id);
+    }
+    assert!(remaining.contains("sg-web"));
+}
+
+#[tokio::test]
+async fn test_clean_out_of_order() {
+    let backends = fixture().backends();
+    let backend = &backends["eu-west-1"];
+    let throttle = Throttle::new(ThrottleConfig::default());
+
+    // Deleting a referenced group before the group referencing it is refused.
+    backend.delete_security_group("sg-d").await.unwrap_err();
+    backend.delete_security_group("sg-a").await.unwrap_err();
+    backend.delete_security_group("sg-f").await.unwrap_err();
+    backend
+        .delete_security_group("sg-default")
+        .await
+        .unwrap_err();
+
+    let report = scan(&[backend], &throttle).await;
+    assert_eq!(report.groups.existing_groups.len(), 12);
+}